@@ -5,35 +5,159 @@
 use nu_ansi_term::{Color::Fixed, Style};
 use zellij_tile::prelude::*;
 
-use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
+use std::cmp::min;
 use std::collections::{BTreeMap, BTreeSet};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use zellij_project_switcher_plugin::core;
+use zellij_project_switcher_plugin::cache::{self, ProjectCache};
+use zellij_project_switcher_plugin::core::{
+    self, Found, Naming, Root, Search, SearchMode, SearchScope,
+};
+use zellij_project_switcher_plugin::discovery::{self, Backend, DiscoveryError, Progress};
+use zellij_project_switcher_plugin::editors::{self, Editor};
+use zellij_project_switcher_plugin::exclude::{self, Exclude};
+use zellij_project_switcher_plugin::fuzzy;
+use zellij_project_switcher_plugin::markers::{Markers, Vcs};
+use zellij_project_switcher_plugin::remote;
+use zellij_project_switcher_plugin::source::{
+    FdSource, FileSource, FoundSource, Listed, PinnedSource, SourceProject, SourceRegistry,
+};
 use zellij_project_switcher_plugin::stack;
+use zellij_project_switcher_plugin::worktree::{self, GitFile, Worktree};
+use zellij_project_switcher_plugin::zoxide::{self, Zoxide};
 
 // Lives in the plugin's /cache mount: keyed by plugin URL, shared across
 // sessions, and persistent — one MRU stack for all instances.
 const STACK_PATH: &str = "/cache/session-stack.v1";
 const TOGGLE_DEBOUNCE_PATH: &str = "/cache/session-stack-toggle.claim";
 const TOGGLE_MESSAGE: &str = "toggle_session";
+// The command backend auto-detection settled on, so later opens skip probing.
+const BACKEND_PATH: &str = "/cache/discovery-backend.v1";
+// The last complete discovery result, shown straight away on the next open.
+const PROJECTS_PATH: &str = "/cache/projects.v1";
+// Paths of projects the user hid from the list.
+const HIDDEN_PATH: &str = "/cache/hidden-projects.v1";
+
+/// Work that needs /host pointed somewhere first, or that waits for the
+/// jobs queued before it.
+#[derive(Clone)]
+enum HostJob {
+    /// Walk a root with the native backend; /host is the root.
+    Walk(Root),
+    /// Read editors' recent folders; /host is the filesystem root.
+    EditorRecents,
+    /// Read the `.git` files of possible worktrees, and their HEADs; /host is
+    /// the filesystem root.
+    GitFiles(Vec<String>),
+    /// Read the origin remotes of the repositories in `remotes_pending`, for
+    /// `naming "remote"`; /host is the filesystem root.
+    GitConfigs,
+    /// Check which of zoxide's directories hold a marker; /host is the
+    /// filesystem root.
+    ZoxideMarkers(Vec<String>),
+    /// Every root is in and the `.git` files and remotes they listed have
+    /// been read: finish the refresh once the paths found are resolved too.
+    Complete,
+}
 
 #[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
 struct State {
     userspace_configuration: BTreeMap<String, String>,
     projects: BTreeMap<String, String>,
-    filtered_projects: BTreeSet<String>,
+    filtered_projects: Vec<String>,
     top_idx: usize,
     sel_idx: usize,
     selected: String,
     search_term: String,
+    // Ctrl-r switches to regular expressions; a term that does not compile
+    // leaves the list as it was and says why.
+    search_mode: SearchMode,
+    search_error: Option<String>,
+    // Ctrl-f cycles through also searching projects' relative and full
+    // paths.
+    search_scope: SearchScope,
     rows: usize,
     cols: usize,
     current_session: Option<String>,
     pending_events: Vec<Event>,
     got_permissions: bool,
     projects_loaded: bool,
+    backend: Backend,
+    // The auto-detected backend read from BACKEND_PATH, until it fails.
+    remembered_backend: Option<Backend>,
+    // Auto-detection candidates still to try for each root whose command is
+    // in flight; the head is the backend currently running.
+    probes: BTreeMap<String, Vec<Backend>>,
+    // Command line in flight for each root, for error reports.
+    discovery_commands: BTreeMap<String, String>,
+    discovery_errors: Vec<DiscoveryError>,
+    progress: Progress,
+    // Repository paths currently listed (from the cache and the refresh in
+    // flight), and those the refresh in flight has found so far, which
+    // replace them once every root is in. Names are derived from the whole
+    // set so colliding directory names can be told apart.
+    paths: BTreeSet<String>,
+    discovered: BTreeSet<String>,
+    naming: Naming,
+    markers: Markers,
+    exclude: Exclude,
+    // Paths of projects hidden with Delete, and whether they are listed
+    // anyway (Tab) so they can be brought back.
+    hidden: BTreeSet<String>,
+    show_hidden: bool,
+    // The VCS each listed path was found with. A directory with several
+    // markers (a colocated jj repository) is listed once, as its preferred VCS.
+    vcs: BTreeMap<String, Vcs>,
+    // Linked worktrees by path; None while the `.git` file is being read.
+    // Listed as `<main>@<branch>` once HEAD has been read.
+    worktrees: BTreeMap<String, Option<Worktree>>,
+    discovered_worktrees: BTreeSet<String>,
+    // The cache the list was seeded from, until the refresh completes.
+    cached: Option<ProjectCache>,
+    // Discovery over the configured roots, registered in `sources` with the
+    // backend of the refresh in flight.
+    fd: FdSource,
+    // Discovery, the editors, zoxide, the `projects_file` list and the
+    // pinned projects, and what they list merged by path. Projects they name
+    // are always listed, and win over a discovered project of the same name.
+    sources: SourceRegistry,
+    listed: BTreeMap<String, Listed>,
+    // Roots still to walk with the native backend, editor recents and `.git`
+    // files to read, and the end of the refresh; the head is the job the
+    // pending change_host_folder request is for, and is only removed once it
+    // is done.
+    host_jobs: Vec<HostJob>,
+    // Editors whose recent folders are listed, and those folders.
+    editors: Vec<Editor>,
+    editor_paths: BTreeSet<String>,
+    // The configured roots and their options, with paths expanded.
+    roots: Vec<Root>,
+    // Where each discovered path really is, once resolved; paths sharing a
+    // real path are listed once. The refresh completes only once no resolve
+    // command is in flight, so the cache never misses a duplicate.
+    real_paths: BTreeMap<String, String>,
+    resolves_pending: usize,
+    // `org/repo` names of projects' origin remotes, for `naming "remote"`,
+    // the git repositories whose `.git/config` has been asked for this
+    // refresh, and those still to be read.
+    remotes: BTreeMap<String, String>,
+    remotes_requested: BTreeSet<String>,
+    remotes_pending: BTreeSet<String>,
+    // The `zoxide` settings, the score of each directory zoxide knows, and
+    // the directories it contributes to the list.
+    zoxide: Option<Zoxide>,
+    zoxide_scores: BTreeMap<String, f64>,
+    zoxide_paths: BTreeSet<String>,
+    // The environment commands run in, for expanding configured paths.
+    env: BTreeMap<String, String>,
+    // Configured paths that could not be expanded, exclude globs that could
+    // not be parsed, and optional sources that are unavailable; unlike
+    // discovery errors these do not stop the result being cached.
+    config_errors: Vec<DiscoveryError>,
+    // The `layout` setting: a built-in layout name or an expanded file path.
+    layout: String,
     // Session-stack tracking (see src/stack.rs). own_session/own_connected
     // come from SessionUpdate's is_current_session entry, independent of the
     // ModeUpdate-driven current_session used by the UI.
@@ -45,39 +169,788 @@ struct State {
 
 impl State {
     pub fn refresh_projects(&mut self) {
-        core::refresh_projects(&self.userspace_configuration, run_command);
+        let roots: Vec<String> = self.roots.iter().map(|r| r.path.clone()).collect();
+        self.discovery_errors.clear();
+        self.discovered.clear();
+        self.discovered_worktrees.clear();
+        self.remotes_requested.clear();
+        self.remotes_pending.clear();
+        self.host_jobs.clear();
+        self.progress = Progress::start(&roots);
+        let backend = match self.backend {
+            Backend::Native => {
+                self.host_jobs = self.roots.iter().cloned().map(HostJob::Walk).collect();
+                Backend::Native
+            }
+            Backend::Auto => {
+                let remembered = discovery::read_backend(Path::new(BACKEND_PATH));
+                let candidates: Vec<Backend> = remembered
+                    .into_iter()
+                    .chain(
+                        discovery::PROBE_ORDER
+                            .iter()
+                            .copied()
+                            .filter(|b| Some(*b) != remembered),
+                    )
+                    .collect();
+                self.remembered_backend = remembered;
+                self.probes = roots
+                    .into_iter()
+                    .map(|root| (root, candidates.clone()))
+                    .collect();
+                candidates[0]
+            }
+            backend => backend,
+        };
+        self.sources.register(FdSource {
+            backend,
+            ..self.fd.clone()
+        });
+        self.sources.load();
+        for (cmd, context) in self.sources.commands() {
+            if context.get("source").is_some_and(|source| source == "fd") {
+                self.discovery_commands
+                    .insert(context["root"].clone(), cmd.join(" "));
+            }
+            let cmd: Vec<&str> = cmd.iter().map(String::as_ref).collect();
+            run_command(&cmd, context);
+        }
+        if !self.editors.is_empty() {
+            self.host_jobs.push(HostJob::EditorRecents);
+        }
+        self.next_host_job();
+        if self.zoxide.is_some() {
+            run_command(
+                &zoxide::QUERY,
+                BTreeMap::from([(String::from("command"), String::from("zoxide_query"))]),
+            );
+        }
+    }
+
+    /// zoxide's directories are in: list those scoring high enough outright,
+    /// and ask which of the rest are projects.
+    fn zoxide_read(&mut self, exit_code: Option<i32>, stdout: &[u8]) {
+        let Some(config) = self.zoxide else {
+            return;
+        };
+        if discovery::is_missing_program(exit_code) {
+            self.config_error(DiscoveryError::MissingZoxide {
+                command: zoxide::QUERY.join(" "),
+            });
+            return;
+        }
+        self.zoxide_scores = zoxide::parse_scores(&String::from_utf8_lossy(stdout));
+        let mut listed = Vec::new();
+        let mut candidates = Vec::new();
+        for (path, score) in &self.zoxide_scores {
+            if config.lists_any(*score) {
+                listed.push(path.clone());
+            } else {
+                candidates.push(path.clone());
+            }
+        }
+        if !candidates.is_empty() {
+            self.queue_host_job(HostJob::ZoxideMarkers(candidates));
+        }
+        self.zoxide_paths.clear();
+        self.zoxide_found(listed);
+    }
+
+    /// /host is the filesystem root: the zoxide directories holding a
+    /// marker are projects.
+    fn zoxide_markers_read(&mut self, candidates: &[String]) {
+        let entries =
+            zoxide::read_markers(Path::new(discovery::HOST_MOUNT), candidates, &self.markers);
+        let output = core::join_output(entries.iter().map(String::as_str));
+        let mut paths: Vec<String> = core::parse_lines(&output, &self.markers)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        paths.extend(core::parse_worktree_lines(&output));
+        self.zoxide_found(paths);
+    }
+
+    fn zoxide_found(&mut self, paths: Vec<String>) {
+        let unresolved: Vec<String> = paths
+            .iter()
+            .filter(|path| !self.real_paths.contains_key(*path))
+            .cloned()
+            .collect();
+        self.resolve_paths(&unresolved);
+        self.zoxide_paths.extend(paths);
+        self.rebuild_projects();
+    }
+
+    /// Read the environment zellij runs commands in: the plugin's own is
+    /// nearly empty, and configured paths are expanded from it.
+    fn read_env() {
+        run_command(
+            &["env"],
+            BTreeMap::from([(String::from("command"), String::from("read_env"))]),
+        );
+    }
+
+    fn env_read(&mut self, exit_code: Option<i32>, stdout: &[u8]) {
+        self.env = std::env::vars().collect();
+        if exit_code == Some(0) {
+            self.env
+                .extend(core::parse_env(&String::from_utf8_lossy(stdout)));
+        }
+        self.expand_config();
+        self.refresh_projects();
+    }
+
+    /// Expand `~` and variables in the configured roots, pinned projects,
+    /// projects file and layout. Entries that cannot be expanded are reported and left out.
+    fn expand_config(&mut self) {
+        let config = &self.userspace_configuration;
+        let env = &self.env;
+        let mut errors = Vec::new();
+        let mut expand = |setting: &str, path: &str| match core::expand_path(path, env) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                errors.push(DiscoveryError::UnexpandedPath {
+                    setting: setting.to_string(),
+                    reason: e.to_string(),
+                });
+                None
+            }
+        };
+        self.roots = core::parse_roots(config)
+            .into_iter()
+            .filter_map(|root| {
+                Some(Root {
+                    path: expand("roots", &root.path)?,
+                    ..root
+                })
+            })
+            .collect();
+        let pinned = core::pinned_projects(config)
+            .into_iter()
+            .filter_map(|(name, path)| Some((name, expand("pinned", &path)?)))
+            .collect();
+        let projects_file = config
+            .get("projects_file")
+            .and_then(|path| expand("projects_file", path));
+        let file = FileSource::from_config(config, projects_file, env);
+        let layout = config.get("layout").map_or("default", String::as_str);
+        self.layout = if core::is_layout_path(layout) {
+            expand("layout", layout).unwrap_or_else(|| String::from("default"))
+        } else {
+            layout.to_string()
+        };
+        for (outer, inner) in core::overlapping_roots(&self.roots) {
+            errors.push(DiscoveryError::OverlappingRoots { outer, inner });
+        }
+        for glob in self.exclude.invalid() {
+            errors.push(DiscoveryError::InvalidGlob { glob: glob.clone() });
+        }
+        self.config_errors = errors;
+        self.register_sources(pinned, file);
+        self.rebuild_projects();
+    }
+
+    /// Start the project sources over from the configuration: discovery
+    /// over the roots, the editors, zoxide, the projects file when one is
+    /// set and the pinned projects.
+    fn register_sources(&mut self, pinned: BTreeMap<String, String>, file: Option<FileSource>) {
+        self.fd = FdSource::from_config(&self.userspace_configuration, &self.roots, self.backend);
+        self.sources.clear();
+        self.sources.register(self.fd.clone());
+        self.sources.register(FoundSource { name: "editors" });
+        self.sources.register(FoundSource { name: "zoxide" });
+        if let Some(file) = file {
+            self.sources.register(file);
+        }
+        self.sources.register(PinnedSource { projects: pinned });
+    }
+
+    fn run_discovery_for_root(&mut self, root: &str, backend: Backend) {
+        let Some(root) = self.roots.iter().find(|r| r.path == root) else {
+            return;
+        };
+        let (cmd, context) = core::discovery_command(root, backend, &self.markers, &self.exclude);
+        self.discovery_commands
+            .insert(root.path.clone(), cmd.join(" "));
+        let cmd: Vec<&str> = cmd.iter().map(String::as_ref).collect();
+        run_command(&cmd, context);
+    }
+
+    /// A root's discovery command has finished. Auto-detection moves on to
+    /// the next backend when this one is not installed, and remembers the
+    /// one that ran.
+    fn discovery_result(
+        &mut self,
+        context: &BTreeMap<String, String>,
+        exit_code: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) {
+        let root = context.get("root").cloned().unwrap_or_default();
+        let backend = context
+            .get("backend")
+            .and_then(|name| Backend::from_name(name))
+            .unwrap_or(Backend::Fd);
+        if self.backend == Backend::Auto && discovery::is_missing_program(exit_code) {
+            self.probe_after(&root, backend);
+            return;
+        }
+        if self.backend == Backend::Auto && self.remembered_backend != Some(backend) {
+            discovery::write_backend(Path::new(BACKEND_PATH), backend);
+            self.remembered_backend = Some(backend);
+        }
+        self.discovery_finished(&root, exit_code, stdout, stderr);
+    }
+
+    fn discovery_finished(
+        &mut self,
+        root: &str,
+        exit_code: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) {
+        let command = self.discovery_commands.remove(root).unwrap_or_default();
+        match exit_code {
+            Some(0) => (),
+            Some(code) if !discovery::is_missing_program(exit_code) => {
+                self.discovery_errors.push(DiscoveryError::Failed {
+                    command: command.clone(),
+                    exit_code: code,
+                    stderr: String::from_utf8_lossy(stderr).into_owned(),
+                });
+            }
+            _ => {
+                self.discovery_errors.push(DiscoveryError::MissingProgram {
+                    command: command.clone(),
+                });
+            }
+        }
+        // Keep whatever the command did find, even when it failed part way.
+        let (output, invalid) = discovery::decode_output(stdout);
+        if invalid {
+            self.discovery_errors
+                .push(DiscoveryError::InvalidOutput { command });
+        }
+        self.merge_projects(root, &output);
+        self.root_finished(root);
+    }
+
+    /// Auto-detection: `failed` is not installed, so try the next candidate
+    /// for this root. A remembered backend that stopped working is forgotten.
+    fn probe_after(&mut self, root: &str, failed: Backend) {
+        if self.remembered_backend == Some(failed) {
+            discovery::forget_backend(Path::new(BACKEND_PATH));
+            self.remembered_backend = None;
+        }
+        let next = self.probes.get_mut(root).and_then(|candidates| {
+            candidates.retain(|b| *b != failed);
+            candidates.first().copied()
+        });
+        if let Some(backend) = next {
+            self.run_discovery_for_root(root, backend);
+            return;
+        }
+        self.probes.remove(root);
+        self.discovery_commands.remove(root);
+        if !self.discovery_errors.contains(&DiscoveryError::NoBackend) {
+            self.discovery_errors.push(DiscoveryError::NoBackend);
+        }
+        self.root_finished(root);
+    }
+
+    fn root_finished(&mut self, root: &str) {
+        self.probes.remove(root);
+        self.progress.finish(root);
+        self.projects_loaded = true;
+        if self.progress.is_done()
+            && !self
+                .host_jobs
+                .iter()
+                .any(|job| matches!(job, HostJob::Complete))
+        {
+            self.queue_host_job(HostJob::Complete);
+        }
+    }
+
+    /// Every root is in: drop projects that were not found again and cache
+    /// the result. When a root failed its projects may merely be unreachable,
+    /// so the list and cache are left as they are.
+    fn discovery_complete(&mut self) {
+        // Either way the list is no longer only what the cache held.
+        self.cached = None;
+        if !self.discovery_errors.is_empty() {
+            return;
+        }
+        let discovered = &self.discovered;
+        self.vcs.retain(|path, _| discovered.contains(path));
+        let editor_paths = &self.editor_paths;
+        let zoxide_paths = &self.zoxide_paths;
+        self.real_paths.retain(|path, _| {
+            discovered.contains(path) || editor_paths.contains(path) || zoxide_paths.contains(path)
+        });
+        self.remotes.retain(|path, _| discovered.contains(path));
+        self.write_cache();
+        let found = &self.discovered_worktrees;
+        self.worktrees.retain(|path, _| found.contains(path));
+        if self.paths != self.discovered {
+            self.paths = self.discovered.clone();
+        }
+        self.rebuild_projects();
+    }
+
+    fn write_cache(&self) {
+        let roots = core::roots(&self.userspace_configuration);
+        cache::write_cache(
+            Path::new(PROJECTS_PATH),
+            &ProjectCache::new(
+                &roots,
+                &core::name_root_projects(
+                    &core::dedupe_by_real_path(&self.discovered, &self.real_paths),
+                    self.naming,
+                    &self.roots,
+                    &self.remotes,
+                ),
+                &self.vcs,
+                &self.remotes,
+            ),
+        );
+    }
+
+    /// Native discovery walks one root at a time: re-point /host at it and
+    /// wait for `HostFolderChanged` before reading. Editor recents and `.git`
+    /// files are read the same way, with /host at the filesystem root, and
+    /// the refresh completes once the jobs before it and every path resolve
+    /// are done.
+    fn next_host_job(&mut self) {
+        while self.resolves_pending == 0
+            && matches!(self.host_jobs.first(), Some(HostJob::Complete))
+        {
+            self.host_jobs.remove(0);
+            self.discovery_complete();
+        }
+        match self.host_jobs.first() {
+            Some(HostJob::Walk(root)) => change_host_folder(PathBuf::from(&root.path)),
+            Some(
+                HostJob::EditorRecents
+                | HostJob::GitFiles(_)
+                | HostJob::GitConfigs
+                | HostJob::ZoxideMarkers(_),
+            ) => {
+                change_host_folder(PathBuf::from("/"));
+            }
+            None if self.backend == Backend::Native => self.projects_loaded = true,
+            Some(HostJob::Complete) | None => (),
+        }
+    }
+
+    /// Queue `job` ahead of the end of the refresh, which waits for it,
+    /// starting it when nothing else is in flight.
+    fn queue_host_job(&mut self, job: HostJob) {
+        let at = self
+            .host_jobs
+            .iter()
+            .position(|job| matches!(job, HostJob::Complete))
+            .unwrap_or(self.host_jobs.len());
+        self.host_jobs.insert(at, job);
+        if at == 0 {
+            self.next_host_job();
+        }
+    }
+
+    /// /host points where the job at the head of the queue needs it: do the
+    /// job and start the next one.
+    fn host_job_ready(&mut self) {
+        match self.host_jobs[0].clone() {
+            HostJob::Walk(root) => {
+                let entries = discovery::walk_repositories(
+                    Path::new(discovery::HOST_MOUNT),
+                    &root,
+                    &self.markers,
+                    &self.exclude,
+                );
+                let output = core::join_output(entries.iter().map(String::as_str));
+                self.merge_projects(&root.path, &output);
+                self.root_finished(&root.path);
+            }
+            HostJob::EditorRecents => self.read_editor_recents(),
+            HostJob::GitFiles(paths) => self.git_files_read(&paths, true),
+            HostJob::GitConfigs => self.git_configs_read(true),
+            HostJob::ZoxideMarkers(candidates) => self.zoxide_markers_read(&candidates),
+            HostJob::Complete => (),
+        }
+        self.host_jobs.remove(0);
+        self.next_host_job();
+    }
+
+    /// /host could not be re-pointed for the job at the head of the queue:
+    /// do without what it would have read and start the next one.
+    fn host_job_failed(&mut self, reason: String) {
+        match self.host_jobs[0].clone() {
+            HostJob::Walk(root) => {
+                self.root_finished(&root.path);
+                self.discovery_errors.push(DiscoveryError::UnreadableRoot {
+                    root: root.path,
+                    reason,
+                });
+            }
+            HostJob::EditorRecents => {
+                self.config_error(DiscoveryError::UnreadableEditors { reason });
+            }
+            HostJob::GitFiles(paths) => self.git_files_read(&paths, false),
+            HostJob::GitConfigs => self.git_configs_read(false),
+            // zoxide lists only the directories scoring high enough.
+            HostJob::ZoxideMarkers(_) | HostJob::Complete => (),
+        }
+        self.host_jobs.remove(0);
+        self.next_host_job();
+    }
+
+    /// /host is the filesystem root: list the editors' recent folders.
+    fn read_editor_recents(&mut self) {
+        let Some(home) = self.env.get("HOME") else {
+            self.config_error(DiscoveryError::UnreadableEditors {
+                reason: String::from("`HOME` is not set"),
+            });
+            return;
+        };
+        self.editor_paths =
+            editors::read_recent(Path::new(discovery::HOST_MOUNT), home, &self.editors);
+        let unresolved: Vec<String> = self
+            .editor_paths
+            .iter()
+            .filter(|path| !self.real_paths.contains_key(*path))
+            .cloned()
+            .collect();
+        self.resolve_paths(&unresolved);
+        self.rebuild_projects();
+    }
+
+    /// Report a problem with an optional source once, however often it
+    /// recurs.
+    fn config_error(&mut self, error: DiscoveryError) {
+        if !self.config_errors.contains(&error) {
+            self.config_errors.push(error);
+        }
+    }
+
+    /// Ask for the real locations of `paths`; see `core::resolve_command`.
+    fn resolve_paths(&mut self, paths: &[String]) {
+        if !paths.is_empty() {
+            self.resolves_pending += 1;
+            run_command(
+                &core::resolve_command(paths)
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<&str>>(),
+                BTreeMap::from([(String::from("command"), String::from("resolve_paths"))]),
+            );
+        }
+    }
+
+    /// `root`'s discovery output is in: list its projects and read the
+    /// `.git` files it found.
+    fn merge_projects(&mut self, root: &str, output: &str) {
+        // Keyed by root alone, so the output replaces what the root listed
+        // last time whichever backend produced it.
+        let context = BTreeMap::from([
+            (String::from("source"), String::from("fd")),
+            (String::from("root"), root.to_string()),
+        ]);
+        let projects = self
+            .sources
+            .receive(&context, output)
+            .map(<[SourceProject]>::to_vec)
+            .unwrap_or_default();
+        let mut unresolved = Vec::new();
+        for project in projects {
+            if !self.real_paths.contains_key(&project.path) {
+                unresolved.push(project.path.clone());
+            }
+            self.project_found(project.path, project.vcs);
+        }
+        self.resolve_paths(&unresolved);
+        let git_files = core::parse_worktree_lines(&self.fd.filter_output(root, output));
+        for path in &git_files {
+            self.discovered_worktrees.insert(path.clone());
+            self.worktrees.entry(path.clone()).or_insert(None);
+        }
+        if !git_files.is_empty() {
+            self.queue_host_job(HostJob::GitFiles(git_files));
+        }
+        self.rebuild_projects();
+    }
+
+    fn project_found(&mut self, path: String, vcs: Option<Vcs>) {
+        // The first marker this refresh finds for a path replaces what the
+        // cache said; further markers only refine it.
+        let known = self
+            .vcs
+            .get(&path)
+            .copied()
+            .filter(|_| self.discovered.contains(&path));
+        match (known, vcs) {
+            (Some(known), Some(vcs)) => {
+                self.vcs.insert(path.clone(), known.prefer(vcs));
+            }
+            (None, Some(vcs)) => {
+                self.vcs.insert(path.clone(), vcs);
+            }
+            (Some(_), None) => (),
+            (None, None) => {
+                self.vcs.remove(&path);
+            }
+        }
+        if self.naming == Naming::Remote
+            && vcs == Some(Vcs::Git)
+            && self.remotes_requested.insert(path.clone())
+        {
+            self.remotes_pending.insert(path.clone());
+            if !self
+                .host_jobs
+                .iter()
+                .any(|job| matches!(job, HostJob::GitConfigs))
+            {
+                self.queue_host_job(HostJob::GitConfigs);
+            }
+        }
+        self.discovered.insert(path.clone());
+        self.paths.insert(path);
+    }
+
+    /// /host is the filesystem root: read the `.git` files discovery listed.
+    /// When /host could not be re-pointed `read` is false and each is taken
+    /// for an ordinary repository, as one whose `.git` cannot be read is.
+    fn git_files_read(&mut self, paths: &[String], read: bool) {
+        let mut unresolved = Vec::new();
+        for path in paths {
+            let git_file = if read {
+                worktree::read_git_file(Path::new(discovery::HOST_MOUNT), path)
+            } else {
+                GitFile::Repository
+            };
+            match git_file {
+                GitFile::Repository => {
+                    self.worktrees.remove(path);
+                    self.discovered_worktrees.remove(path);
+                    if !self.real_paths.contains_key(path) {
+                        unresolved.push(path.clone());
+                    }
+                    self.project_found(path.clone(), Some(Vcs::Git));
+                }
+                GitFile::Worktree(worktree) => {
+                    self.worktrees.insert(path.clone(), Some(worktree));
+                }
+                // Submodules have `.git` files too; they are not listed.
+                GitFile::Other => {
+                    self.worktrees.remove(path);
+                }
+            }
+        }
+        self.resolve_paths(&unresolved);
+        self.rebuild_projects();
+    }
+
+    /// /host is the filesystem root: name the repositories waiting in
+    /// `remotes_pending` after their origin remote, or by directory when
+    /// they have none. When /host could not be re-pointed `read` is false
+    /// and they all keep their directory names.
+    fn git_configs_read(&mut self, read: bool) {
+        for path in std::mem::take(&mut self.remotes_pending) {
+            let name = if read {
+                remote::read_remote_name(Path::new(discovery::HOST_MOUNT), &path)
+            } else {
+                None
+            };
+            match name {
+                Some(name) => self.remotes.insert(path, name),
+                None => self.remotes.remove(&path),
+            };
+        }
+        self.rebuild_projects();
+    }
+
+    /// A project source's command has finished. A projects file that cannot
+    /// be read lists nothing and is reported.
+    fn source_read(
+        &mut self,
+        context: &BTreeMap<String, String>,
+        exit_code: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) {
+        let mut output = String::new();
+        if exit_code == Some(0) {
+            output = String::from_utf8_lossy(stdout).into_owned();
+        } else if let Some(path) = context.get("path") {
+            // cat says `cat: <path>: <reason>`.
+            let stderr = String::from_utf8_lossy(stderr);
+            let reason = match stderr.trim().rsplit(": ").next() {
+                Some(reason) if !reason.is_empty() => reason.to_string(),
+                _ => format!("`cat` exited with {exit_code:?}"),
+            };
+            self.config_error(DiscoveryError::UnreadableProjectsFile {
+                path: path.clone(),
+                reason,
+            });
+        }
+        self.sources.receive(context, &output);
+        self.rebuild_projects();
+    }
+
+    /// Re-derive the name to path map from the project sources' merged
+    /// lists and the resolved worktrees.
+    fn rebuild_projects(&mut self) {
+        // The roots' outputs are listed already; `paths` adds what the cache
+        // held and the repositories behind `.git` files, with the VCS their
+        // markers settled on.
+        let discovered = self
+            .paths
+            .iter()
+            .map(|path| SourceProject {
+                vcs: self.vcs.get(path).copied(),
+                ..SourceProject::new(path)
+            })
+            .collect();
+        self.sources.list("fd", discovered);
+        let found =
+            |paths: &BTreeSet<String>| paths.iter().map(|p| SourceProject::new(p)).collect();
+        self.sources.list("editors", found(&self.editor_paths));
+        self.sources.list("zoxide", found(&self.zoxide_paths));
+        self.listed = self.sources.merged();
+        let paths: BTreeSet<String> = self
+            .listed
+            .iter()
+            .filter(|(_, listed)| listed.name.is_none())
+            .map(|(path, _)| path.clone())
+            .collect();
+        let paths = core::dedupe_by_real_path(&paths, &self.real_paths);
+        self.projects = core::name_root_projects(&paths, self.naming, &self.roots, &self.remotes);
+        let worktrees: Vec<(String, String)> = self
+            .worktrees
+            .iter()
+            .filter_map(|(path, worktree)| {
+                let worktree = worktree.as_ref().filter(|w| w.branch.is_some())?;
+                let main_name = self
+                    .projects
+                    .iter()
+                    .find(|(_, p)| **p == worktree.main)
+                    .map_or_else(
+                        || worktree.main.rsplit('/').next().unwrap_or_default(),
+                        |(name, _)| name.as_str(),
+                    );
+                Some((worktree.name(main_name), path.clone()))
+            })
+            .collect();
+        self.projects.extend(worktrees);
+        self.projects.extend(self.named_projects());
+        self.refilter();
+    }
+
+    /// The projects the sources list by name.
+    fn named_projects(&self) -> Vec<(String, String)> {
+        self.listed
+            .iter()
+            .filter_map(|(path, listed)| Some((listed.name.clone()?, path.clone())))
+            .collect()
+    }
+
+    /// Whether a project is listed under this name by the `pinned` setting.
+    fn is_pinned(&self, name: &str) -> bool {
+        self.projects.get(name).is_some_and(|path| {
+            self.listed.get(path).is_some_and(|listed| {
+                listed.source == "pinned" && listed.name.as_deref() == Some(name)
+            })
+        })
+    }
+
+    /// The VCS of a discovered project; None for projects only a source
+    /// lists, worktrees and projects found by a marker file.
+    fn vcs_of(&self, name: &str) -> Option<Vcs> {
+        self.projects
+            .get(name)
+            .and_then(|path| self.vcs.get(path))
+            .copied()
+    }
+
+    /// The host of a project checked out below a ghq root.
+    fn host_of(&self, name: &str) -> Option<&str> {
+        let path = self.projects.get(name)?;
+        core::root_of(&self.roots, path)?.host(path)
+    }
+
+    /// Whether a project is in an editor's recently opened list.
+    fn is_editor_recent(&self, name: &str) -> bool {
+        self.projects
+            .get(name)
+            .is_some_and(|path| self.editor_paths.contains(path))
+    }
+
+    fn is_hidden(&self, name: &str) -> bool {
+        self.projects
+            .get(name)
+            .is_some_and(|path| self.hidden.contains(path))
+    }
+
+    /// Hide the selected project, or bring it back when hidden projects are
+    /// being shown.
+    fn toggle_hidden(&mut self) {
+        let Some(path) = self.projects.get(&self.selected).cloned() else {
+            return;
+        };
+        if !self.hidden.remove(&path) {
+            self.hidden.insert(path);
+        }
+        exclude::write_hidden(Path::new(HIDDEN_PATH), &self.hidden);
+        self.refilter();
+    }
+
+    fn is_worktree(&self, name: &str) -> bool {
+        self.projects
+            .get(name)
+            .is_some_and(|path| self.worktrees.contains_key(path))
+    }
+
+    /// Re-apply the filter after the project list changed, keeping the
+    /// selected project selected as the list changes around it.
+    fn refilter(&mut self) {
+        self.update_filtered();
+        if let Some(position) = self
+            .filtered_projects
+            .iter()
+            .position(|p| p == &self.selected)
+        {
+            self.sel_idx = position;
+        }
+        self.update_selected(0, 0);
     }
 
     pub fn handle_key(&mut self, key: &KeyWithModifier) -> bool {
         eprintln!("into handle_ley with: {key:?}");
 
         if let BareKey::Enter = key.bare_key {
-            let default = "default".to_string();
-            let layout = self
-                .userspace_configuration
-                .get("layout")
-                .unwrap_or(&default);
+            let layout = if core::is_layout_path(&self.layout) {
+                LayoutInfo::File(self.layout.clone(), LayoutMetadata::default())
+            } else {
+                LayoutInfo::BuiltIn(self.layout.clone())
+            };
 
             if let Some(cwd) = self.projects.get(&self.selected) {
+                let session = core::session_name(&self.selected);
                 if self
                     .current_session
                     .clone()
-                    .is_some_and(|cs| cs.eq(&self.selected))
+                    .is_some_and(|cs| cs.eq(&session))
                 {
                     eprintln!("Refusing to launch current session");
                 } else {
                     // Eager push so the stack is correct even if the target
                     // session's attach snapshot lags behind the switch.
                     let mut stack = stack::read_stack(Path::new(STACK_PATH));
-                    if stack.push_top(&self.selected) {
+                    if stack.push_top(&session) {
                         stack::write_stack(Path::new(STACK_PATH), &stack);
                     }
                     hide_self();
-                    switch_session_with_layout(
-                        Some(self.selected.as_str()),
-                        LayoutInfo::BuiltIn(layout.into()),
-                        Some(cwd.into()),
-                    );
+                    switch_session_with_layout(Some(session.as_str()), layout, Some(cwd.into()));
                 }
             }
             return true;
@@ -86,6 +959,15 @@ impl State {
             self.handle_backspace();
             return true;
         }
+        if let BareKey::Delete = key.bare_key {
+            self.toggle_hidden();
+            return true;
+        }
+        if let BareKey::Tab = key.bare_key {
+            self.show_hidden = !self.show_hidden;
+            self.refilter();
+            return true;
+        }
         if let BareKey::Esc = key.bare_key {
             close_self();
             return true;
@@ -100,6 +982,16 @@ impl State {
             self.update_selected(0, 1);
             return true;
         }
+        if key.is_key_with_ctrl_modifier(BareKey::Char('f')) {
+            self.search_scope = self.search_scope.next();
+            self.refilter();
+            return true;
+        }
+        if key.is_key_with_ctrl_modifier(BareKey::Char('r')) {
+            self.search_mode = self.search_mode.toggled();
+            self.refilter();
+            return true;
+        }
         if let BareKey::Char(char) = key.bare_key {
             self.update_search_term(char);
             return true;
@@ -138,72 +1030,150 @@ impl State {
         if new_idx > 0 {
             new_idx -= up;
         }
-        if new_idx < self.filtered_projects.len() {
-            new_idx += down;
-        }
+        new_idx = min(
+            new_idx + down,
+            self.filtered_projects.len().saturating_sub(1),
+        );
         self.sel_idx = new_idx;
-        if self.sel_idx >= self.rows {
-            self.top_idx = self.sel_idx - self.rows;
-        } else {
-            self.top_idx = 0;
+        // Scroll only as far as it takes to keep the selection in view.
+        if self.sel_idx < self.top_idx {
+            self.top_idx = self.sel_idx;
+        } else if self.sel_idx >= self.top_idx + self.rows {
+            self.top_idx = (self.sel_idx + 1).saturating_sub(self.rows);
         }
-        if let Some(k) = self.filtered_projects.iter().nth(self.sel_idx) {
+        self.top_idx = min(
+            self.top_idx,
+            self.filtered_projects.len().saturating_sub(self.rows),
+        );
+        if let Some(k) = self.filtered_projects.get(self.sel_idx) {
             self.selected.clone_from(k);
         }
     }
 
-    pub fn update_filtered(&mut self) {
-        let regex_str = self
-            .search_term
-            .chars()
-            .enumerate()
-            .fold(String::new(), |acc, (i, c)| {
-                if i != 0 {
-                    format!("{acc}.*?{c}")
-                } else {
-                    format!("{acc}{c}")
+    /// A project's line in the list: its name with the matched characters
+    /// highlighted, what kind of project it is, and for a project found by
+    /// its path the part of the path that matched.
+    fn project_line(&self, p: &str, selected: bool, search: Option<&Search>) -> String {
+        // Git is the common case; only other VCSs are called out.
+        let marker = if self.is_hidden(p) {
+            color_bold(GRAY_DARK, " (hidden)")
+        } else if self.is_pinned(p) {
+            color_bold(GRAY_DARK, " (pinned)")
+        } else if self.is_editor_recent(p) {
+            color_bold(GRAY_DARK, " (editor)")
+        } else {
+            match self.vcs_of(p) {
+                Some(vcs) if vcs != Vcs::Git => {
+                    color_bold(GRAY_DARK, &format!(" ({})", vcs.name()))
                 }
-            });
-        let regex = RegexBuilder::new(&regex_str)
-            .case_insensitive(true)
-            .build()
-            .unwrap();
-        self.filtered_projects =
-            self.projects
-                .keys()
-                .filter(|p| regex.is_match(p))
-                .fold(BTreeSet::new(), |mut c, v| {
-                    c.insert(v.to_owned());
-                    c
-                });
+                _ => String::new(),
+            }
+        };
+        let found = search.and_then(|search| search.find_project(p, self.path_text(p).as_deref()));
+        // Worktrees sit indented under their main checkout.
+        let indent = if self.is_worktree(p) { "  " } else { "" };
+        let (prefix, style, matched) = if selected {
+            let style = Style::new().fg(Fixed(GREEN)).bold();
+            ("> ", style, style.underline())
+        } else {
+            let style = Style::new().fg(Fixed(WHITE)).bold();
+            ("  ", style, Style::new().fg(Fixed(ORANGE)).bold())
+        };
+        // A project found by its path shows the part that matched.
+        let (name, path) = match found {
+            Some(Found::Name(m)) => (highlight(p, &m.positions, style, matched), String::new()),
+            Some(Found::Path(m)) => {
+                let text = self.path_text(p).unwrap_or_default();
+                let (segment, positions) = core::matched_segment(&text, &m.positions);
+                let dim = Style::new().fg(Fixed(GRAY_DARK));
+                (
+                    style.paint(p).to_string(),
+                    format!(
+                        "{}{}",
+                        dim.paint("  "),
+                        highlight(&segment, &positions, dim, matched)
+                    ),
+                )
+            }
+            None => (style.paint(p).to_string(), String::new()),
+        };
+        format!(
+            "{}{name}{marker}{path}",
+            style.paint(format!("{prefix}{indent}"))
+        )
     }
 
-    fn do_lines(&mut self, lines: &str) -> BTreeMap<String, String> {
-        let init = State::default_projects();
-        eprintln!("Default Projects: {:?}", self.projects);
-        lines.lines().fold(init, State::split)
+    /// The text of a project the search scope searches besides its name.
+    fn path_text(&self, name: &str) -> Option<String> {
+        let path = self.projects.get(name)?;
+        self.search_scope.text(path, &self.roots)
     }
 
-    fn split(mut acc: BTreeMap<String, String>, line: &str) -> BTreeMap<String, String> {
-        let re = Regex::new(r"^(?<path>.*\/(?<name>[^/]+))\/.git\/$").unwrap();
-
-        match re.captures(line) {
-            Some(caps) => {
-                acc.insert(caps["name"].into(), caps["path"].into());
-                acc
-            }
-            None => acc,
+    /// The line under the header: the search as it was understood, term by
+    /// term, and where it looks, or why a regular expression is invalid.
+    fn search_status(&self) -> String {
+        if let Some(error) = &self.search_error {
+            return color_bold(RED, &format!("regex: {error}"));
         }
+        let mut terms = Search::new(&self.search_term, self.search_mode, self.search_scope)
+            .map(|search| search.terms())
+            .unwrap_or_default();
+        if terms.is_empty() && self.search_mode == SearchMode::Regex {
+            terms.push(String::from("regex"));
+        }
+        if self.search_scope != SearchScope::Name {
+            terms.push(format!("in names and {}s", self.search_scope.name()));
+        }
+        color_bold(GRAY_DARK, &terms.join(", "))
     }
 
-    fn default_projects() -> BTreeMap<String, String> {
-        let mut projects = BTreeMap::new();
-        projects.insert("default".into(), "/Users/idavies".into());
-        projects.insert(
-            "zps-dev".into(),
-            "/Users/idavies/Documents/GitHub/zellij-project-switcher".into(),
-        );
-        projects
+    /// List the projects matching the search, best match first. Without a
+    /// search, or between equally good matches, higher zoxide scores come
+    /// first.
+    pub fn update_filtered(&mut self) {
+        let search = match Search::new(&self.search_term, self.search_mode, self.search_scope) {
+            Ok(search) => search,
+            Err(error) => {
+                self.search_error = Some(error);
+                return;
+            }
+        };
+        self.search_error = None;
+        let hosts = search.hosts();
+        let projects = &self.projects;
+        let scores = &self.zoxide_scores;
+        let score = |name: &str| {
+            projects
+                .get(name)
+                .and_then(|path| scores.get(path))
+                .copied()
+                .unwrap_or(0.0)
+        };
+        let main_of = |name: &str| {
+            let worktree = self.worktrees.get(projects.get(name)?)?.as_ref()?;
+            worktree.main_name(name).map(String::from)
+        };
+        let candidates = self
+            .projects
+            .keys()
+            .filter(|p| {
+                hosts.is_empty()
+                    || self.host_of(p).is_some_and(|host| {
+                        let host = host.to_lowercase();
+                        hosts.iter().any(|h| host.contains(&h.to_lowercase()))
+                    })
+            })
+            .filter(|p| self.show_hidden || !self.is_hidden(p))
+            .map(String::as_str);
+        self.filtered_projects = search
+            .rank_with_paths(
+                candidates,
+                |name| self.path_text(name),
+                |a, b| zoxide::score_order(a, b, score, main_of),
+            )
+            .into_iter()
+            .map(String::from)
+            .collect();
     }
 
     /// Cmd-Tab-style toggle: switch to the most recent live session that
@@ -306,20 +1276,51 @@ impl State {
                     self.own_session = Some(own.name.clone());
                 }
             }
-            Event::RunCommandResult(Some(status), stdin, _stdout, _data) => {
-                if status == 0 {
-                    let mut v = match std::str::from_utf8(&stdin) {
-                        Ok(val) => self.do_lines(val),
-
-                        Err(_) => State::default_projects(),
-                    };
-                    self.projects.append(&mut v);
-                    self.update_filtered();
-                    self.update_selected(0, 0);
-                    self.projects_loaded = true;
+            Event::RunCommandResult(_exit_code, stdout, _stderr, context)
+                if context.get("command").is_some_and(|c| c == "resolve_paths") =>
+            {
+                // Paths that could not be resolved are simply not merged.
+                self.real_paths
+                    .extend(core::parse_resolved(&String::from_utf8_lossy(&stdout)));
+                self.resolves_pending = self.resolves_pending.saturating_sub(1);
+                self.rebuild_projects();
+                if matches!(self.host_jobs.first(), Some(HostJob::Complete)) {
+                    self.next_host_job();
                 }
                 should_render = true;
             }
+            Event::RunCommandResult(exit_code, stdout, _stderr, context)
+                if context.get("command").is_some_and(|c| c == "read_env") =>
+            {
+                self.env_read(exit_code, &stdout);
+                should_render = true;
+            }
+            Event::RunCommandResult(exit_code, stdout, _stderr, context)
+                if context.get("command").is_some_and(|c| c == "zoxide_query") =>
+            {
+                self.zoxide_read(exit_code, &stdout);
+                should_render = true;
+            }
+            Event::RunCommandResult(exit_code, stdout, stderr, context)
+                if context
+                    .get("command")
+                    .is_some_and(|c| c == "project_source") =>
+            {
+                self.source_read(&context, exit_code, &stdout, &stderr);
+                should_render = true;
+            }
+            Event::RunCommandResult(exit_code, stdout, stderr, context) => {
+                self.discovery_result(&context, exit_code, &stdout, &stderr);
+                should_render = true;
+            }
+            Event::HostFolderChanged(_path) if !self.host_jobs.is_empty() => {
+                self.host_job_ready();
+                should_render = true;
+            }
+            Event::FailedToChangeHostFolder(error) if !self.host_jobs.is_empty() => {
+                self.host_job_failed(error.unwrap_or_else(|| String::from("unknown error")));
+                should_render = true;
+            }
             _ => (),
         }
         should_render
@@ -361,17 +1362,41 @@ impl ZellijPlugin for State {
                 PermissionType::ReadApplicationState,
                 PermissionType::ChangeApplicationState,
                 PermissionType::RunCommands,
+                PermissionType::FullHdAccess,
             ]);
             return;
         }
-        self.projects = State::default_projects();
-        self.filtered_projects = self.projects.keys().fold(BTreeSet::new(), |mut c, v| {
-            c.insert(v.to_owned());
-            c
-        });
+        self.backend = Backend::from_config(&self.userspace_configuration);
+        self.naming = Naming::from_config(&self.userspace_configuration);
+        self.editors = Editor::from_config(&self.userspace_configuration);
+        self.zoxide = Zoxide::from_config(&self.userspace_configuration);
+        self.markers = Markers::from_config(&self.userspace_configuration);
+        self.exclude = Exclude::from_config(&self.userspace_configuration);
+        self.hidden = exclude::read_hidden(Path::new(HIDDEN_PATH));
+        // Show the last discovery result straight away; the refresh started
+        // once permissions are granted brings it up to date.
+        // Paths are used as written until the environment has been read.
+        self.roots = core::parse_roots(&self.userspace_configuration);
+        self.register_sources(core::pinned_projects(&self.userspace_configuration), None);
+        self.sources.load();
+        self.layout = self
+            .userspace_configuration
+            .get("layout")
+            .map_or_else(|| String::from("default"), Clone::clone);
+        let roots = core::roots(&self.userspace_configuration);
+        if let Some(cached) =
+            cache::read_cache(Path::new(PROJECTS_PATH)).filter(|c| c.matches_roots(&roots))
+        {
+            self.paths = cached.projects.values().cloned().collect();
+            self.vcs.clone_from(&cached.vcs);
+            self.remotes.clone_from(&cached.remotes);
+            self.projects_loaded = true;
+            self.cached = Some(cached);
+        }
+        self.rebuild_projects();
         self.top_idx = 0;
         self.sel_idx = 0;
-        self.selected = "default".to_string();
+        self.selected = String::new();
         self.search_term = String::new();
         self.rows = 10;
         self.cols = 40;
@@ -379,16 +1404,20 @@ impl ZellijPlugin for State {
         // we need the ReadApplicationState permission to receive the ModeUpdate and TabUpdate
         // events
         // we need the ChangeApplicationState permission to open sessions
+        // we need the FullHdAccess permission to walk roots with the native backend
         request_permission(&[
             PermissionType::ReadApplicationState,
             PermissionType::ChangeApplicationState,
             PermissionType::RunCommands,
+            PermissionType::FullHdAccess,
         ]);
         subscribe(&[
             EventType::ModeUpdate,
             EventType::Key,
             EventType::CustomMessage,
             EventType::RunCommandResult,
+            EventType::HostFolderChanged,
+            EventType::FailedToChangeHostFolder,
             EventType::SessionUpdate,
             EventType::PermissionRequestResult,
         ]);
@@ -422,9 +1451,10 @@ impl ZellijPlugin for State {
                 self.handle_event(ev.unwrap());
             }
 
-            // perform an initial load of projects...
+            // perform an initial load of projects, once paths can be
+            // expanded...
             if !self.tracker_mode {
-                self.refresh_projects();
+                State::read_env();
             }
         }
 
@@ -439,11 +1469,32 @@ impl ZellijPlugin for State {
     fn render(&mut self, rows: usize, cols: usize) {
         const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-        // Reserve 3 rows for header (blank, status, blank) and 1 for footer (version)
-        self.rows = rows.saturating_sub(4);
+        let error_lines: Vec<String> = self
+            .config_errors
+            .iter()
+            .chain(&self.discovery_errors)
+            .flat_map(DiscoveryError::report)
+            .collect();
+
+        // Reserve 3 rows for header (blank, status, blank), 1 for footer (version)
+        // and any discovery error reports
+        self.rows = rows.saturating_sub(4 + error_lines.len());
         self.cols = cols;
         self.update_selected(0, 0);
-        println!();
+        match (self.progress.summary(), &self.cached) {
+            (Some(summary), Some(cached)) => println!(
+                "{}",
+                color_bold(
+                    GRAY_DARK,
+                    &format!(
+                        "{summary} (showing results from {} ago)",
+                        cached.age(cache::now_secs())
+                    )
+                )
+            ),
+            (Some(summary), None) => println!("{}", color_bold(GRAY_DARK, &summary)),
+            (None, _) => println!(),
+        }
         println!(
             "Current: [{}] :: Filter: [{}] :: Open project: [{}]?",
             color_bold(
@@ -456,7 +1507,17 @@ impl ZellijPlugin for State {
             color_bold(ORANGE, &self.search_term),
             color_bold(GREEN, &self.selected)
         );
-        println!();
+        println!("{}", self.search_status());
+
+        for line in &error_lines {
+            // Summary lines start at the margin; details are indented.
+            let color = if line.starts_with(' ') {
+                GRAY_DARK
+            } else {
+                RED
+            };
+            println!("{}", color_bold(color, line));
+        }
 
         if !self.projects_loaded {
             println!("{}", color_bold(CYAN, "Loading projects..."));
@@ -469,22 +1530,22 @@ impl ZellijPlugin for State {
             self.sel_idx,
             self.top_idx
         );
-        eprintln!("Defaults {:?}", State::default_projects());
 
+        let search = Search::new(&self.search_term, self.search_mode, self.search_scope).ok();
         let mut lines_printed = 0;
-        for (i, p) in self.filtered_projects.iter().enumerate() {
-            if i < self.rows {
-                if i == self.sel_idx {
-                    println!("{}", color_bold(GREEN, &format!("> {p}").to_string()));
-                    lines_printed += 1;
-                } else {
-                    // we'll show self.rows items at a time - adjust for rows
-                    if i >= self.top_idx && i < self.top_idx + self.rows {
-                        println!("{}", color_bold(WHITE, &format!("  {p}").to_string()));
-                        lines_printed += 1;
-                    }
-                }
-            }
+        // we'll show self.rows items at a time, from top_idx
+        for (i, p) in self
+            .filtered_projects
+            .iter()
+            .enumerate()
+            .skip(self.top_idx)
+            .take(self.rows)
+        {
+            println!(
+                "{}",
+                self.project_line(p, i == self.sel_idx, search.as_ref())
+            );
+            lines_printed += 1;
         }
 
         // Fill remaining lines to push version to bottom
@@ -516,3 +1577,82 @@ pub const ORANGE: u8 = 166;
 fn color_bold(color: u8, text: &str) -> String {
     format!("{}", Style::new().fg(Fixed(color)).bold().paint(text))
 }
+
+/// `text` in `style`, with the characters at `positions` in `matched`.
+fn highlight(text: &str, positions: &[usize], style: Style, matched: Style) -> String {
+    fuzzy::segments(text, positions)
+        .into_iter()
+        .map(|(segment, is_match)| {
+            let style = if is_match { matched } else { style };
+            style.paint(segment).to_string()
+        })
+        .collect()
+}
+
+// Tests run without zellij: the host functions discovery calls do nothing.
+#[cfg(test)]
+fn run_command(_cmd: &[&str], _context: BTreeMap<String, String>) {}
+
+#[cfg(test)]
+fn change_host_folder(_path: PathBuf) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn a_failed_root_still_ends_showing_cached_results() {
+        let roots = [String::from("/a"), String::from("/b")];
+        let mut state = State {
+            roots: roots.iter().map(|root| Root::new(root)).collect(),
+            progress: Progress::start(&roots),
+            cached: Some(ProjectCache::default()),
+            ..State::default()
+        };
+        state.discovery_finished("/a", Some(0), b"", b"");
+        assert!(state.cached.is_some());
+        state.discovery_finished("/b", Some(1), b"", b"permission denied");
+        assert!(state.progress.is_done());
+        assert_eq!(state.discovery_errors.len(), 1);
+        assert!(state.cached.is_none());
+    }
+
+    #[test]
+    fn a_roots_output_goes_through_the_fd_source() {
+        let roots = [String::from("/a")];
+        let mut state = State {
+            roots: roots.iter().map(|root| Root::new(root)).collect(),
+            progress: Progress::start(&roots),
+            ..State::default()
+        };
+        state.register_sources(BTreeMap::new(), None);
+        state.discovery_finished("/a", Some(0), b"/a/api/.git/\0/a/web/.jj/\0", b"");
+        assert_eq!(state.listed["/a/api"].source, "fd");
+        assert_eq!(state.listed["/a/web"].vcs, Some(Vcs::Jujutsu));
+        assert_eq!(
+            state.projects.keys().collect::<Vec<_>>(),
+            vec!["api", "web"]
+        );
+    }
+
+    #[test]
+    fn an_unreadable_projects_file_is_reported() {
+        let mut state = State::default();
+        let file = FileSource {
+            path: String::from("/home/me/projects.txt"),
+            env: BTreeMap::new(),
+        };
+        state.register_sources(BTreeMap::new(), Some(file));
+        let (_, context) = state.sources.commands().remove(0);
+        let stderr = b"cat: /home/me/projects.txt: No such file or directory\n";
+        state.source_read(&context, Some(1), b"", stderr);
+        assert_eq!(
+            state.config_errors,
+            vec![DiscoveryError::UnreadableProjectsFile {
+                path: String::from("/home/me/projects.txt"),
+                reason: String::from("No such file or directory"),
+            }]
+        );
+        assert!(state.projects.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::markers::Vcs;
+use crate::stack::{escape_field, tmp_path, unescape_field};
+
+const HEADER: &str = "projects.v1";
+
+/// The last discovery result, persisted so the switcher can show a list the
+/// moment it opens while a fresh search runs in the background.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectCache {
+    /// Seconds since the Unix epoch when the projects were discovered.
+    pub timestamp: u64,
+    /// The roots the projects were discovered from; a cache for different
+    /// roots is not reused.
+    pub roots: Vec<String>,
+    /// Project name to path, as `State::projects` holds them.
+    pub projects: BTreeMap<String, String>,
+    /// Project path to the VCS it was found with.
+    pub vcs: BTreeMap<String, Vcs>,
+    /// Project path to the `org/repo` name of its remote, for
+    /// `Naming::Remote`.
+    pub remotes: BTreeMap<String, String>,
+}
+
+impl ProjectCache {
+    #[must_use]
+    pub fn new(
+        roots: &[String],
+        projects: &BTreeMap<String, String>,
+        vcs: &BTreeMap<String, Vcs>,
+        remotes: &BTreeMap<String, String>,
+    ) -> ProjectCache {
+        ProjectCache {
+            timestamp: now_secs(),
+            roots: roots.to_vec(),
+            projects: projects.clone(),
+            vcs: vcs.clone(),
+            remotes: remotes.clone(),
+        }
+    }
+
+    /// Parse a cache file. Total, like the session stack: a missing header
+    /// yields None, unknown or malformed lines are skipped.
+    #[must_use]
+    pub fn parse(contents: &str) -> Option<ProjectCache> {
+        let mut lines = contents.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let mut cache = ProjectCache::default();
+        for line in lines {
+            let Some((kind, rest)) = line.split_once(' ') else {
+                continue;
+            };
+            match kind {
+                "timestamp" => cache.timestamp = rest.parse().unwrap_or(0),
+                "root" => cache.roots.push(unescape_field(rest)),
+                "project" => {
+                    if let Some((name, path)) = rest.split_once('\t') {
+                        cache
+                            .projects
+                            .insert(unescape_field(name), unescape_field(path));
+                    }
+                }
+                "vcs" => {
+                    if let Some((vcs, path)) = rest.split_once('\t') {
+                        if let Some(vcs) = Vcs::from_name(vcs) {
+                            cache.vcs.insert(unescape_field(path), vcs);
+                        }
+                    }
+                }
+                "remote" => {
+                    if let Some((name, path)) = rest.split_once('\t') {
+                        cache
+                            .remotes
+                            .insert(unescape_field(path), unescape_field(name));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Some(cache)
+    }
+
+    #[must_use]
+    pub fn serialize(&self) -> String {
+        let mut out = format!("{HEADER}\ntimestamp {}\n", self.timestamp);
+        for root in &self.roots {
+            let _ = writeln!(out, "root {}", escape_field(root));
+        }
+        for (name, path) in &self.projects {
+            let _ = writeln!(
+                out,
+                "project {}\t{}",
+                escape_field(name),
+                escape_field(path)
+            );
+        }
+        for (path, vcs) in &self.vcs {
+            let _ = writeln!(out, "vcs {}\t{}", vcs.name(), escape_field(path));
+        }
+        for (path, name) in &self.remotes {
+            let _ = writeln!(out, "remote {}\t{}", escape_field(name), escape_field(path));
+        }
+        out
+    }
+
+    /// Whether this cache was computed from exactly `roots`.
+    #[must_use]
+    pub fn matches_roots(&self, roots: &[String]) -> bool {
+        self.roots == roots
+    }
+
+    /// How long ago the cache was written, coarsely: `42s`, `5m`, `3h`, `2d`.
+    #[must_use]
+    pub fn age(&self, now: u64) -> String {
+        let secs = now.saturating_sub(self.timestamp);
+        match secs {
+            0..=59 => format!("{secs}s"),
+            60..=3599 => format!("{}m", secs / 60),
+            3600..=86399 => format!("{}h", secs / 3600),
+            _ => format!("{}d", secs / 86400),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, or 0 when the clock is unavailable.
+#[must_use]
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Read the cache at `path`. Any failure yields None: the cache only ever
+/// saves a wait.
+#[must_use]
+pub fn read_cache(path: &Path) -> Option<ProjectCache> {
+    ProjectCache::parse(&std::fs::read_to_string(path).ok()?)
+}
+
+/// Persist the cache atomically, as `stack::write_stack` does. Errors are
+/// logged and swallowed.
+pub fn write_cache(path: &Path, cache: &ProjectCache) {
+    let tmp = tmp_path(path);
+    let result = std::fs::write(&tmp, cache.serialize()).and_then(|()| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        eprintln!("project-cache: failed to persist {}: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::scratch;
+
+    fn sample() -> ProjectCache {
+        ProjectCache {
+            timestamp: 1_700_000_000,
+            roots: vec![String::from("~/src"), String::from("~/work")],
+            projects: BTreeMap::from([
+                (String::from("alpha"), String::from("/src/alpha")),
+                (String::from("beta"), String::from("/work/beta")),
+            ]),
+            vcs: BTreeMap::from([
+                (String::from("/src/alpha"), Vcs::Git),
+                (String::from("/work/beta"), Vcs::Jujutsu),
+            ]),
+            remotes: BTreeMap::from([(String::from("/src/alpha"), String::from("acme/alpha"))]),
+        }
+    }
+
+    #[test]
+    fn parse_round_trips_serialize() {
+        let cache = sample();
+        assert_eq!(ProjectCache::parse(&cache.serialize()), Some(cache));
+    }
+
+    #[test]
+    fn paths_with_line_breaks_round_trip() {
+        let odd = String::from("/src/two\nlines\tand \\n tab");
+        let cache = ProjectCache {
+            roots: vec![odd.clone()],
+            projects: BTreeMap::from([(String::from("a\tb"), odd.clone())]),
+            vcs: BTreeMap::from([(odd.clone(), Vcs::Git)]),
+            remotes: BTreeMap::from([(odd.clone(), String::from("acme/odd"))]),
+            ..sample()
+        };
+        let text = cache.serialize();
+        assert_eq!(text.lines().count(), 6);
+        assert_eq!(ProjectCache::parse(&text), Some(cache));
+    }
+
+    #[test]
+    fn parse_requires_the_header() {
+        assert_eq!(ProjectCache::parse(""), None);
+        assert_eq!(ProjectCache::parse("timestamp 1\n"), None);
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        let cache = ProjectCache::parse(
+            "projects.v1\ngarbage\nproject no-tab\ntimestamp x\nroot ~\nvcs svn\t/x\nremote a/b\n",
+        )
+        .unwrap();
+        assert_eq!(cache.timestamp, 0);
+        assert_eq!(cache.roots, vec!["~"]);
+        assert!(cache.projects.is_empty());
+        assert!(cache.vcs.is_empty());
+        assert!(cache.remotes.is_empty());
+    }
+
+    #[test]
+    fn matches_roots_compares_in_order() {
+        let cache = sample();
+        assert!(cache.matches_roots(&[String::from("~/src"), String::from("~/work")]));
+        assert!(!cache.matches_roots(&[String::from("~/work"), String::from("~/src")]));
+        assert!(!cache.matches_roots(&[String::from("~/src")]));
+    }
+
+    #[test]
+    fn age_is_coarse() {
+        let cache = sample();
+        assert_eq!(cache.age(cache.timestamp + 5), "5s");
+        assert_eq!(cache.age(cache.timestamp + 300), "5m");
+        assert_eq!(cache.age(cache.timestamp + 7200), "2h");
+        assert_eq!(cache.age(cache.timestamp + 3 * 86400), "3d");
+        assert_eq!(cache.age(0), "0s");
+    }
+
+    #[test]
+    fn write_then_read_round_trips() {
+        let dir = scratch("zps-cache-test", &[]);
+        let path = dir.join("projects.v1");
+        write_cache(&path, &sample());
+        assert_eq!(read_cache(&path), Some(sample()));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn read_cache_of_missing_file_is_none() {
+        assert_eq!(read_cache(Path::new("/nonexistent/dir/projects.v1")), None);
+    }
+}
@@ -1,52 +1,1225 @@
 use std::{
     cmp::min,
     collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
 };
 
-use regex::RegexBuilder;
+use crate::discovery::Backend;
+use crate::exclude::Exclude;
+use crate::fuzzy::{self, Anchor};
+use crate::markers::{Markers, Vcs};
 
-pub fn refresh_projects<RC>(config: &BTreeMap<String, String>, mut f: RC)
+/// The command a command backend runs to search `root` for `markers`,
+/// skipping `exclude`, and its context. Anything other than fdfind or find
+/// runs fd. find can only prune by name; `Exclude::filter_output` enforces
+/// the rest. Both print NUL-terminated entries (see `split_output`).
+#[must_use]
+pub fn discovery_command(
+    root: &Root,
+    backend: Backend,
+    markers: &Markers,
+    exclude: &Exclude,
+) -> (Vec<String>, BTreeMap<String, String>) {
+    let backend = match backend {
+        Backend::Fdfind | Backend::Find => backend,
+        _ => Backend::Fd,
+    };
+    let options = BTreeMap::from([
+        (String::from("command"), String::from("refresh_projects")),
+        (String::from("backend"), String::from(backend.name())),
+        (String::from("root"), root.path.clone()),
+    ]);
+
+    let max_depth = root.max_depth().to_string();
+    let mut cmd: Vec<String> = Vec::from([String::from(backend.program().unwrap_or("fd"))]);
+    if backend == Backend::Find {
+        if root.follow {
+            cmd.push(String::from("-L"));
+        }
+        cmd.push(root.path.clone());
+        let names = exclude.name_globs();
+        // The root itself is never excluded.
+        let min_depth = root
+            .min_depth()
+            .or_else(|| (!names.is_empty()).then_some(1));
+        if let Some(min_depth) = min_depth {
+            cmd.push(String::from("-mindepth"));
+            cmd.push(min_depth.to_string());
+        }
+        cmd.push(String::from("-maxdepth"));
+        cmd.push(max_depth);
+        if !names.is_empty() {
+            cmd.push(String::from("("));
+            for (i, name) in names.iter().enumerate() {
+                if i > 0 {
+                    cmd.push(String::from("-o"));
+                }
+                cmd.push(String::from("-name"));
+                cmd.push((*name).to_string());
+            }
+            cmd.extend([")", "-prune", "-o"].iter().map(|s| (*s).to_string()));
+        }
+        cmd.extend(markers.find_args());
+        cmd.extend(find_print_args());
+    } else {
+        cmd.extend(["-0", "-Htd", "-tf"].iter().map(|s| (*s).to_string()));
+        if let Some(min_depth) = root.min_depth() {
+            cmd.push(format!("--min-depth={min_depth}"));
+        }
+        cmd.push(format!("--max-depth={max_depth}"));
+        if root.follow {
+            cmd.push(String::from("--follow"));
+        }
+        for glob in exclude.globs() {
+            cmd.push(String::from("--exclude"));
+            cmd.push(glob.to_string());
+        }
+        cmd.push(markers.fd_pattern());
+        cmd.push(root.path.clone());
+    }
+    (cmd, options)
+}
+
+/// The `find` arguments printing matches in fd's shape. Directories get a
+/// trailing slash, as fd does, so `.git` files (worktrees) can be told
+/// apart. Entries end in NUL so paths may contain newlines.
+#[must_use]
+fn find_print_args() -> Vec<String> {
+    [
+        "(", "-type", "d", "-exec", "printf", "%s/\\0", "{}", "+", "-o", "-type", "f", "-print0",
+        ")",
+    ]
+    .iter()
+    .map(|s| (*s).to_string())
+    .collect()
+}
+
+/// How deep below a root projects are looked for unless its options say
+/// otherwise: the root itself and the directories directly inside it.
+pub const DEFAULT_DEPTH: usize = 1;
+
+/// How deep projects are below a root with the ghq layout:
+/// `<host>/<org>/<repo>`.
+pub const GHQ_DEPTH: usize = 3;
+
+/// How the projects below a root are arranged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RootLayout {
+    /// Anywhere within the root's depth.
+    #[default]
+    Plain,
+    /// As ghq checks repositories out, `<host>/<org>/<repo>`: projects are
+    /// exactly `GHQ_DEPTH` levels down, named `org/repo` and filterable by
+    /// host.
+    Ghq,
+}
+
+/// A configured root and its discovery options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Root {
+    pub path: String,
+    /// How many levels below the root a project can be; 1 finds `<root>/x`,
+    /// 3 finds `<root>/<host>/<org>/<repo>`.
+    pub depth: usize,
+    /// Follow symlinked directories.
+    pub follow: bool,
+    /// Look inside hidden directories.
+    pub hidden: bool,
+    /// Prepended to the names of this root's projects.
+    pub prefix: String,
+    pub layout: RootLayout,
+}
+
+impl Root {
+    #[must_use]
+    pub fn new(path: &str) -> Root {
+        Root {
+            path: path.to_string(),
+            depth: DEFAULT_DEPTH,
+            follow: false,
+            hidden: true,
+            prefix: String::new(),
+            layout: RootLayout::Plain,
+        }
+    }
+
+    /// Parse one `roots` entry: a path, optionally followed by options in
+    /// brackets, e.g. `~/src/github.com[depth=3,follow,hidden=false,prefix=gh/]`.
+    /// Flags may be given bare (`follow`) or as `true`/`false`. Unknown
+    /// options and invalid values are ignored. `layout=ghq` fixes the depth
+    /// at `GHQ_DEPTH`.
+    #[must_use]
+    pub fn parse(entry: &str) -> Root {
+        let entry = entry.trim();
+        let Some((path, options)) = entry
+            .strip_suffix(']')
+            .and_then(|rest| rest.rsplit_once('['))
+        else {
+            return Root::new(entry);
+        };
+        let mut root = Root::new(path.trim());
+        for option in options.split(',') {
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (option.trim(), None),
+            };
+            let flag = match value {
+                None | Some("true") => Some(true),
+                Some("false") => Some(false),
+                Some(_) => None,
+            };
+            match (key, value, flag) {
+                ("depth", Some(value), _) => {
+                    if let Ok(depth) = value.parse() {
+                        root.depth = depth;
+                    }
+                }
+                ("follow", _, Some(flag)) => root.follow = flag,
+                ("hidden", _, Some(flag)) => root.hidden = flag,
+                ("prefix", Some(value), _) => root.prefix = value.to_string(),
+                ("layout", Some("ghq"), _) => root.layout = RootLayout::Ghq,
+                ("layout", Some("plain"), _) => root.layout = RootLayout::Plain,
+                _ => (),
+            }
+        }
+        if root.layout == RootLayout::Ghq {
+            root.depth = GHQ_DEPTH;
+        }
+        root
+    }
+
+    #[must_use]
+    pub fn layout_name(&self) -> &'static str {
+        match self.layout {
+            RootLayout::Plain => "plain",
+            RootLayout::Ghq => "ghq",
+        }
+    }
+
+    /// The search depth of the markers themselves, as fd counts it: one
+    /// level below the deepest project.
+    #[must_use]
+    pub fn max_depth(&self) -> usize {
+        self.depth + 1
+    }
+
+    /// The least depth a marker can be at, for layouts that put projects at
+    /// one depth only.
+    #[must_use]
+    pub fn min_depth(&self) -> Option<usize> {
+        match self.layout {
+            RootLayout::Plain => None,
+            RootLayout::Ghq => Some(self.max_depth()),
+        }
+    }
+
+    /// The host a project of a ghq root is checked out from: the first
+    /// directory below the root. None for other layouts.
+    #[must_use]
+    pub fn host<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.layout != RootLayout::Ghq || !self.contains(path) {
+            return None;
+        }
+        let relative = &path[self.path.trim_end_matches('/').len()..];
+        let mut parts = relative.split('/').filter(|p| !p.is_empty());
+        let host = parts.next()?;
+        (parts.count() == GHQ_DEPTH - 1).then_some(host)
+    }
+
+    /// Whether `path` is this root or lies below it.
+    #[must_use]
+    pub fn contains(&self, path: &str) -> bool {
+        let root = self.path.trim_end_matches('/');
+        path.strip_prefix(root)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Drop discovery output entries below a hidden directory when this root
+    /// leaves them out. The marker itself (`.git`) is hidden by nature, so
+    /// only the directories leading to it count. Searching needs hidden
+    /// entries enabled to see the markers at all, so this is applied to the
+    /// output.
+    #[must_use]
+    pub fn filter_output(&self, output: &str) -> String {
+        let root = self.path.trim_end_matches('/');
+        join_output(split_output(output).into_iter().filter(|entry| {
+            if self.hidden {
+                return true;
+            }
+            let relative = entry.strip_prefix(root).unwrap_or(entry);
+            let relative = relative.trim_end_matches('/');
+            let dirs = relative.rsplit_once('/').map_or("", |(dirs, _)| dirs);
+            !dirs.split('/').any(|d| d.starts_with('.'))
+        }))
+    }
+}
+
+/// The `roots` entry that parses back into this root, with every option
+/// given, e.g. `~/src[depth=1,follow=false,hidden=true,prefix=,layout=plain]`.
+impl fmt::Display for Root {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[depth={},follow={},hidden={},prefix={},layout={}]",
+            self.path,
+            self.depth,
+            self.follow,
+            self.hidden,
+            self.prefix,
+            self.layout_name()
+        )
+    }
+}
+
+/// The configured `roots`, split on the `:`s outside their options'
+/// brackets, with their options. Defaults to the home directory.
+#[must_use]
+pub fn parse_roots(config: &BTreeMap<String, String>) -> Vec<Root> {
+    let roots = config.get("roots").map_or("~", String::as_str);
+    let mut parsed = Vec::new();
+    let mut start = 0;
+    let mut depth = 0_usize;
+    for (i, c) in roots.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => {
+                parsed.push(Root::parse(&roots[start..i]));
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parsed.push(Root::parse(&roots[start..]));
+    parsed
+}
+
+/// The configured `roots` with every option spelled out, as the project
+/// cache records them: a cache made with other options is stale.
+#[must_use]
+pub fn roots(config: &BTreeMap<String, String>) -> Vec<String> {
+    parse_roots(config).iter().map(Root::to_string).collect()
+}
+
+/// The `pinned` setting: `name=path` pairs separated by `;`, e.g.
+/// `home=~;dotfiles=~/.dotfiles`. Entries without a name or path are skipped.
+#[must_use]
+pub fn pinned_projects(config: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    config
+        .get("pinned")
+        .map(String::as_str)
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|entry| {
+            let (name, path) = entry.split_once('=')?;
+            let (name, path) = (name.trim(), path.trim());
+            if name.is_empty() || path.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), path.to_string()))
+        })
+        .collect()
+}
+
+/// Why a configured path could not be expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpandError {
+    /// The path uses a variable (or `~`, through `HOME`) that is not set.
+    Unset { path: String, variable: String },
+    /// A `${` without its closing `}`.
+    Unterminated { path: String },
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpandError::Unset { path, variable } => {
+                write!(f, "cannot expand `{path}`: `{variable}` is not set")
+            }
+            ExpandError::Unterminated { path } => {
+                write!(f, "cannot expand `{path}`: `${{` is not closed")
+            }
+        }
+    }
+}
+
+/// Expand a configured path: a leading `~` or `~/` becomes `$HOME`, and
+/// `$VAR` and `${VAR}` are replaced from `env`. Anything else, including
+/// `~user` and a `$` not followed by a name, is kept as written.
+///
+/// # Errors
+///
+/// When a variable the path uses is not set in `env`, or `${` is not closed.
+pub fn expand_path(path: &str, env: &BTreeMap<String, String>) -> Result<String, ExpandError> {
+    let lookup = |variable: &str| {
+        env.get(variable)
+            .cloned()
+            .ok_or_else(|| ExpandError::Unset {
+                path: path.to_string(),
+                variable: variable.to_string(),
+            })
+    };
+    let mut expanded = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&lookup("HOME")?);
+        rest = &rest[1..];
+    }
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        if let Some(braced) = after.strip_prefix('{') {
+            let end = braced.find('}').ok_or_else(|| ExpandError::Unterminated {
+                path: path.to_string(),
+            })?;
+            expanded.push_str(&lookup(&braced[..end])?);
+            rest = &braced[end + 1..];
+        } else {
+            let len = after
+                .char_indices()
+                .find(|&(i, c)| {
+                    !(c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()))
+                })
+                .map_or(after.len(), |(i, _)| i);
+            if len == 0 {
+                expanded.push('$');
+            } else {
+                expanded.push_str(&lookup(&after[..len])?);
+            }
+            rest = &after[len..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Parse the output of `env`: one `NAME=value` per line. Lines that do not
+/// start with a variable name, such as continuations of multi-line values,
+/// are skipped.
+#[must_use]
+pub fn parse_env(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            let valid = name
+                .chars()
+                .enumerate()
+                .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+            (valid && !name.is_empty()).then(|| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Whether the `layout` setting names a layout file rather than a built-in
+/// layout: it is a path, or ends in `.kdl`.
+#[must_use]
+pub fn is_layout_path(layout: &str) -> bool {
+    layout.contains('/')
+        || layout.starts_with('~')
+        || layout.starts_with('$')
+        || Path::new(layout)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("kdl"))
+}
+
+/// How project names are derived from repository paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Naming {
+    /// The directory name; repositories sharing one get the shortest parent
+    /// suffix that tells them apart, e.g. `work/api` and `personal/api`.
+    #[default]
+    Shortest,
+    /// Always the parent and directory name, e.g. `work/api`, lengthened
+    /// further only on collision.
+    Parent,
+    /// The full path.
+    Path,
+    /// `org/repo` from the `origin` remote in `.git/config`, falling back to
+    /// the shortest directory name for projects without one.
+    Remote,
+}
+
+impl Naming {
+    /// Read the `naming` setting: `shortest` (default), `parent`, `path` or
+    /// `remote`.
+    #[must_use]
+    pub fn from_config(config: &BTreeMap<String, String>) -> Naming {
+        match config.get("naming").map(|n| n.trim()) {
+            Some("parent") => Naming::Parent,
+            Some("path") => Naming::Path,
+            Some("remote") => Naming::Remote,
+            _ => Naming::Shortest,
+        }
+    }
+
+    fn min_components(self) -> usize {
+        match self {
+            Naming::Parent => 2,
+            Naming::Shortest | Naming::Path | Naming::Remote => 1,
+        }
+    }
+}
+
+/// Split discovery output into its entries. Output containing a NUL is
+/// NUL-separated (`fd -0`, `find -print0`) and its entries are kept as they
+/// are, newlines and all. Anything else is taken as lines, as older caches
+/// and hand-run commands print them, without carriage returns or trailing
+/// whitespace. Empty entries are skipped either way.
+#[must_use]
+pub fn split_output(output: &str) -> Vec<&str> {
+    if output.contains('\0') {
+        output.split('\0').filter(|e| !e.is_empty()).collect()
+    } else {
+        output
+            .lines()
+            .map(str::trim_end)
+            .filter(|e| !e.is_empty())
+            .collect()
+    }
+}
+
+/// Join entries into NUL-terminated output, the shape `split_output` reads
+/// back unchanged.
+#[must_use]
+pub fn join_output<'a, I>(entries: I) -> String
 where
-    RC: FnMut(&[&str], BTreeMap<String, String>),
+    I: IntoIterator<Item = &'a str>,
 {
-    let options = BTreeMap::from([(String::from("command"), String::from("refresh_projects"))]);
+    entries.into_iter().fold(String::new(), |mut acc, entry| {
+        acc.push_str(entry);
+        acc.push('\0');
+        acc
+    })
+}
 
-    let mut cmd: Vec<String> = Vec::from([
-        String::from("fd"),
-        String::from("-Htd"),
-        String::from("--max-depth=2"),
-        String::from("^\\.git$"),
-    ]);
+/// Read one discovery entry as the directory it was found in, the marker's
+/// name and whether the marker is a directory. Directories end in `/` (any
+/// number of them); repeated slashes and `.` components in the path are
+/// tidied. None for an entry without a parent directory.
+fn parse_entry(entry: &str) -> Option<(String, &str, bool)> {
+    let trimmed = entry.trim_end_matches('/');
+    let directory = trimmed.len() < entry.len();
+    let (path, marker) = trimmed.rsplit_once('/')?;
+    let path = path.trim_end_matches('/');
+    if path.is_empty() || marker.is_empty() {
+        return None;
+    }
+    Some((normalise_path(path), marker, directory))
+}
+
+/// Extract project paths and their VCS from discovery output in the fd
+/// shape (`<repo>/<marker>/` for a directory, `<repo>/<marker>` for a file),
+/// split by `split_output`. Projects found by a marker that is not a VCS
+/// have no VCS. A `.git` file is a worktree (see `parse_worktree_lines`);
+/// entries for other names than `markers` are ignored.
+///
+/// Unless `markers` keeps nested projects, a non-VCS project inside another
+/// project in the same output is dropped, so a Cargo workspace is listed once
+/// rather than once per crate.
+#[must_use]
+pub fn parse_lines(output: &str, markers: &Markers) -> Vec<(String, Option<Vcs>)> {
+    let found: Vec<(String, Option<Vcs>)> = split_output(output)
+        .into_iter()
+        .filter_map(parse_entry)
+        .filter(|(_, marker, directory)| {
+            markers.contains(marker) && (*marker != ".git" || *directory)
+        })
+        .map(|(path, marker, _)| (path, Vcs::from_marker(marker)))
+        .collect();
+    if markers.nested() {
+        return found;
+    }
+    let paths: BTreeSet<&str> = found.iter().map(|(p, _)| p.as_str()).collect();
+    let nested = |path: &str| {
+        let mut ancestor = path;
+        while let Some((parent, _)) = ancestor.rsplit_once('/') {
+            if paths.contains(parent) {
+                return true;
+            }
+            ancestor = parent;
+        }
+        false
+    };
+    found
+        .iter()
+        .filter(|(path, vcs)| vcs.is_some() || !nested(path))
+        .cloned()
+        .collect()
+}
+
+/// Extract worktree paths from discovery output: entries for a `.git` file,
+/// `<worktree>/.git` without the trailing slash directories get. fd releases
+/// that print no slash after directories make repositories look like this
+/// too; reading the "file" tells them apart.
+#[must_use]
+pub fn parse_worktree_lines(output: &str) -> Vec<String> {
+    split_output(output)
+        .into_iter()
+        .filter_map(parse_entry)
+        .filter(|(_, marker, directory)| *marker == ".git" && !directory)
+        .map(|(path, _, _)| path)
+        .collect()
+}
+
+/// Tidy a path lexically: repeated slashes, `.` components and trailing
+/// slashes go, and `..` removes the component before it. Symlinks are not
+/// resolved; see `resolve_command` for that.
+#[must_use]
+pub fn normalise_path(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => (),
+            ".." if parts.last().is_some_and(|p| *p != "..") => {
+                parts.pop();
+            }
+            ".." if absolute => (),
+            _ => parts.push(part),
+        }
+    }
+    match (absolute, parts.is_empty()) {
+        (true, _) => format!("/{}", parts.join("/")),
+        (false, true) => String::from("."),
+        (false, false) => parts.join("/"),
+    }
+}
+
+/// A command printing each path's real location, with symlinks resolved, as
+/// `<path>\t<real path>` lines. Paths that cannot be entered are left out.
+#[must_use]
+pub fn resolve_command(paths: &[String]) -> Vec<String> {
+    let mut cmd: Vec<String> = [
+        "sh",
+        "-c",
+        "for p; do r=$(cd -P -- \"$p\" 2>/dev/null && pwd -P) && printf '%s\\t%s\\n' \"$p\" \"$r\"; done",
+        "sh",
+    ]
+    .iter()
+    .map(|s| (*s).to_string())
+    .collect();
+    cmd.extend(paths.iter().cloned());
+    cmd
+}
+
+/// Parse the output of `resolve_command` into path to real path.
+#[must_use]
+pub fn parse_resolved(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (path, real) = line.split_once('\t')?;
+            (!path.is_empty() && real.starts_with('/'))
+                .then(|| (path.to_string(), normalise_path(real)))
+        })
+        .collect()
+}
+
+/// Keep one path per real location, so a repository reached through a
+/// symlink or through two overlapping roots is listed once. The path that is
+/// itself the real location is kept, otherwise the shortest. Paths without a
+/// known real location stand for themselves.
+#[must_use]
+pub fn dedupe_by_real_path(
+    paths: &BTreeSet<String>,
+    real_paths: &BTreeMap<String, String>,
+) -> BTreeSet<String> {
+    let mut by_real: BTreeMap<&str, &str> = BTreeMap::new();
+    for path in paths {
+        let real = real_paths.get(path).map_or(path.as_str(), String::as_str);
+        let keep = by_real.entry(real).or_insert(path);
+        let rank = |p: &str| (p != real, p.len(), p.to_string());
+        if rank(path) < rank(keep) {
+            *keep = path;
+        }
+    }
+    by_real.into_values().map(String::from).collect()
+}
+
+/// Pairs of roots where the second lies inside the first (or both are the
+/// same), whose projects would be found twice.
+#[must_use]
+pub fn overlapping_roots(roots: &[Root]) -> Vec<(String, String)> {
+    let mut overlaps = Vec::new();
+    for (i, outer) in roots.iter().enumerate() {
+        for (j, inner) in roots.iter().enumerate() {
+            let inner_path = normalise_path(&inner.path);
+            let outer_path = normalise_path(&outer.path);
+            let same = inner_path == outer_path;
+            if i != j && (!same || i < j) && Root::new(&outer_path).contains(&inner_path) {
+                overlaps.push((outer.path.clone(), inner.path.clone()));
+            }
+        }
+    }
+    overlaps
+}
+
+/// Name each repository path, keeping every name unique so no repository
+/// hides another in the name-keyed project map.
+#[must_use]
+pub fn name_projects(paths: &BTreeSet<String>, naming: Naming) -> BTreeMap<String, String> {
+    if naming == Naming::Path {
+        return paths.iter().map(|p| (p.clone(), p.clone())).collect();
+    }
+    let components: Vec<Vec<&str>> = paths
+        .iter()
+        .map(|p| p.split('/').filter(|c| !c.is_empty()).collect())
+        .collect();
+    let suffix = |parts: &[&str], n: usize| parts[parts.len().saturating_sub(n)..].join("/");
+    // Only repositories with the same directory name can ever collide.
+    let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, parts) in components.iter().enumerate() {
+        groups
+            .entry(parts.last().copied().unwrap_or_default())
+            .or_default()
+            .push(i);
+    }
+
+    let mut named = BTreeMap::new();
+    for (path, parts) in paths.iter().zip(&components) {
+        let group = &groups[parts.last().copied().unwrap_or_default()];
+        let mut n = naming.min_components();
+        // Lengthen the suffix until no other repository shares it, or the
+        // whole path is used.
+        while n < parts.len()
+            && group.iter().any(|&other| {
+                components[other] != *parts && suffix(&components[other], n) == suffix(parts, n)
+            })
+        {
+            n += 1;
+        }
+        named.insert(suffix(parts, n), path.clone());
+    }
+    named
+}
+
+/// With `Naming::Remote`, give the projects in `named` their remote's
+/// `org/repo` name from `remotes` (path to name). A project keeps its
+/// directory name when another project shares its remote, or when the
+/// remote name is already some other project's name.
+fn apply_remote_names(
+    named: BTreeMap<String, String>,
+    remotes: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut uses: BTreeMap<&str, usize> = BTreeMap::new();
+    for path in named.values() {
+        if let Some(remote) = remotes.get(path) {
+            *uses.entry(remote.as_str()).or_default() += 1;
+        }
+    }
+    let remote_of = |path: &String| remotes.get(path).filter(|r| uses[r.as_str()] == 1);
+    let (renamed, plain): (Vec<_>, Vec<_>) = named
+        .into_iter()
+        .partition(|(_, path)| remote_of(path).is_some());
+    let mut result: BTreeMap<String, String> = plain.into_iter().collect();
+    for (name, path) in renamed {
+        let remote = remote_of(&path).cloned().unwrap_or_default();
+        let name = if result.contains_key(&remote) {
+            name
+        } else {
+            remote
+        };
+        result.insert(name, path);
+    }
+    result
+}
+
+/// The root `path` was found in: the deepest containing one, for nested
+/// roots.
+#[must_use]
+pub fn root_of<'a>(roots: &'a [Root], path: &str) -> Option<&'a Root> {
+    roots
+        .iter()
+        .filter(|r| r.contains(path))
+        .max_by_key(|r| r.path.trim_end_matches('/').len())
+}
+
+/// Name each repository path as `name_projects` does, except that the
+/// projects of a root with a name prefix are named among themselves and get
+/// the prefix, so the prefix alone keeps them apart from other roots'.
+/// Projects of ghq roots are named `org/repo`, with the host added only
+/// when two hosts have the same `org/repo`. `remotes` holds the remote names
+/// `Naming::Remote` uses.
+#[must_use]
+pub fn name_root_projects(
+    paths: &BTreeSet<String>,
+    naming: Naming,
+    roots: &[Root],
+    remotes: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut groups: BTreeMap<(&str, RootLayout), BTreeSet<String>> = BTreeMap::new();
+    for path in paths {
+        let key =
+            root_of(roots, path).map_or(("", RootLayout::Plain), |r| (r.prefix.as_str(), r.layout));
+        groups.entry(key).or_default().insert(path.clone());
+    }
+    groups
+        .into_iter()
+        .flat_map(|((prefix, layout), paths)| {
+            let group_naming = match (layout, naming) {
+                (RootLayout::Ghq, Naming::Shortest | Naming::Remote) => Naming::Parent,
+                _ => naming,
+            };
+            let named = name_projects(&paths, group_naming);
+            let named = if naming == Naming::Remote {
+                apply_remote_names(named, remotes)
+            } else {
+                named
+            };
+            named
+                .into_iter()
+                .map(move |(name, path)| (format!("{prefix}{name}"), path))
+        })
+        .collect()
+}
+
+/// Take `host:<text>` words out of a search term: the hosts a project must
+/// be checked out from (any of them, matched by substring), and the rest of
+/// the term.
+#[must_use]
+pub fn host_filter(term: &str) -> (Vec<&str>, String) {
+    let mut hosts = Vec::new();
+    let mut rest = Vec::new();
+    for word in term.split(' ') {
+        match word.strip_prefix("host:") {
+            Some("") => (),
+            Some(host) => hosts.push(host),
+            None => rest.push(word),
+        }
+    }
+    (hosts, rest.join(" "))
+}
+
+/// The Zellij session name for a project name. Session names cannot contain
+/// `/`, which disambiguated names do, so it is percent-escaped along with
+/// `%` itself: any other replacement would give `work/api` the session of a
+/// project named `work-api`.
+#[must_use]
+pub fn session_name(project: &str) -> String {
+    project.replace('%', "%25").replace('/', "%2F")
+}
+
+/// How the search term is matched against project names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// fzf's extended search syntax; see `Query`.
+    #[default]
+    Fuzzy,
+    /// The term is a regular expression, matched anywhere in the name.
+    Regex,
+}
+
+impl SearchMode {
+    #[must_use]
+    pub fn toggled(self) -> SearchMode {
+        match self {
+            SearchMode::Fuzzy => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::Fuzzy,
+        }
+    }
+}
+
+/// What the search is matched against besides the project name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SearchScope {
+    #[default]
+    Name,
+    /// The project's path below its root; the full path for projects
+    /// outside every root.
+    RelativePath,
+    FullPath,
+}
+
+impl SearchScope {
+    /// The scope after this one, cycling back to `Name`.
+    #[must_use]
+    pub fn next(self) -> SearchScope {
+        match self {
+            SearchScope::Name => SearchScope::RelativePath,
+            SearchScope::RelativePath => SearchScope::FullPath,
+            SearchScope::FullPath => SearchScope::Name,
+        }
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchScope::Name => "name",
+            SearchScope::RelativePath => "relative path",
+            SearchScope::FullPath => "full path",
+        }
+    }
+
+    /// The text of a project at `path` this scope searches besides its
+    /// name; None for `Name`. A root that is itself a project is searched by
+    /// its directory name.
+    #[must_use]
+    pub fn text(self, path: &str, roots: &[Root]) -> Option<String> {
+        match self {
+            SearchScope::Name => None,
+            SearchScope::FullPath => Some(path.to_string()),
+            SearchScope::RelativePath => {
+                let Some(root) = root_of(roots, path) else {
+                    return Some(path.to_string());
+                };
+                let relative = path
+                    .strip_prefix(root.path.trim_end_matches('/'))
+                    .unwrap_or(path)
+                    .trim_matches('/');
+                if relative.is_empty() {
+                    Some(path.rsplit('/').next().unwrap_or(path).to_string())
+                } else {
+                    Some(relative.to_string())
+                }
+            }
+        }
+    }
+}
+
+/// The part of `text` worth showing for a match at `positions`: from the
+/// start of the `/`-separated component holding the first matched character
+/// to the end of the one holding the last, with the positions moved to
+/// match. Positions count characters, as `fuzzy::Match` has them.
+#[must_use]
+pub fn matched_segment(text: &str, positions: &[usize]) -> (String, Vec<usize>) {
+    let chars: Vec<char> = text.chars().collect();
+    let (Some(&first), Some(&last)) = (positions.iter().min(), positions.iter().max()) else {
+        return (text.to_string(), Vec::new());
+    };
+    let start = chars[..first.min(chars.len())]
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |i| i + 1);
+    let end = chars
+        .iter()
+        .skip(last + 1)
+        .position(|c| *c == '/')
+        .map_or(chars.len(), |i| last + 1 + i);
+    (
+        chars[start..end].iter().collect(),
+        positions.iter().map(|p| p - start).collect(),
+    )
+}
+
+/// How a project matched a `Search`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Found {
+    Name(fuzzy::Match),
+    /// The project's name did not match, the text its `SearchScope`
+    /// searches did.
+    Path(fuzzy::Match),
+}
+
+/// How one term of a `Query` matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermKind {
+    /// The characters in order, not necessarily together; see
+    /// `fuzzy::find`.
+    Fuzzy,
+    /// The characters together, where the anchor says.
+    Exact(Anchor),
+}
+
+/// One space-separated term of a `Query`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryTerm {
+    pub kind: TermKind,
+    pub text: String,
+    /// The name must not match.
+    pub negated: bool,
+}
+
+impl QueryTerm {
+    fn find(&self, name: &str) -> Option<fuzzy::Match> {
+        match self.kind {
+            TermKind::Fuzzy => fuzzy::find(&self.text, name),
+            TermKind::Exact(anchor) => fuzzy::find_exact(&self.text, name, anchor),
+        }
+    }
+}
+
+impl fmt::Display for QueryTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            TermKind::Fuzzy => "fuzzy",
+            TermKind::Exact(Anchor::Anywhere) => "exact",
+            TermKind::Exact(Anchor::Start) => "prefix",
+            TermKind::Exact(Anchor::End) => "suffix",
+            TermKind::Exact(Anchor::Whole) => "equal",
+        };
+        let not = if self.negated { "not " } else { "" };
+        write!(f, "{not}{kind} \"{}\"", self.text)
+    }
+}
+
+/// A search in fzf's extended syntax: space-separated terms that must all
+/// match. A plain term matches fuzzily; `'exact` matches its characters
+/// together, `^prefix` at the start of the name, `suffix$` at the end and
+/// `^whole$` the whole name; `!term` excludes names matching `term` exactly
+/// (or as `!^prefix`, `!suffix$`). `\ ` is a space within a term. Case is
+/// ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<QueryTerm>,
+}
+
+impl Query {
+    /// Parse a search term. Markers with nothing to match (a lone `!`, `^`,
+    /// `'` or `$`) are left out.
+    #[must_use]
+    pub fn parse(term: &str) -> Query {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut chars = term.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&' ') => {
+                    word.push(' ');
+                    chars.next();
+                }
+                ' ' => words.push(std::mem::take(&mut word)),
+                c => word.push(c),
+            }
+        }
+        words.push(word);
+        let terms = words
+            .iter()
+            .filter_map(|word| {
+                let (negated, word) = match word.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, word.as_str()),
+                };
+                let (kind, text) = if let Some(text) = word.strip_prefix('\'') {
+                    (TermKind::Exact(Anchor::Anywhere), text)
+                } else {
+                    let (start, word) = match word.strip_prefix('^') {
+                        Some(rest) => (true, rest),
+                        None => (false, word),
+                    };
+                    let (end, word) = match word.strip_suffix('$') {
+                        Some(rest) => (true, rest),
+                        None => (false, word),
+                    };
+                    let kind = match (start, end) {
+                        (true, true) => TermKind::Exact(Anchor::Whole),
+                        (true, false) => TermKind::Exact(Anchor::Start),
+                        (false, true) => TermKind::Exact(Anchor::End),
+                        // As in fzf, a negated plain term is exact.
+                        (false, false) if negated => TermKind::Exact(Anchor::Anywhere),
+                        (false, false) => TermKind::Fuzzy,
+                    };
+                    (kind, word)
+                };
+                (!text.is_empty()).then(|| QueryTerm {
+                    kind,
+                    text: text.to_string(),
+                    negated,
+                })
+            })
+            .collect();
+        Query { terms }
+    }
+
+    /// How `name` matches every term: the scores added up and the positions
+    /// matched by any term. None when a term does not match or a negated
+    /// one does.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<fuzzy::Match> {
+        let mut score = 0;
+        let mut positions = Vec::new();
+        for term in &self.terms {
+            match (term.find(name), term.negated) {
+                (Some(_), true) | (None, false) => return None,
+                (Some(m), false) => {
+                    score += m.score;
+                    positions.extend(m.positions);
+                }
+                (None, true) => (),
+            }
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        Some(fuzzy::Match { score, positions })
+    }
+}
+
+/// A search term ready to match project names: in fuzzy mode `host:` words
+/// (see `host_filter`), then a `Query`, or a regular expression.
+#[derive(Debug, Clone)]
+pub struct Search {
+    hosts: Vec<String>,
+    pattern: Pattern,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Query(Query),
+    Regex(regex::Regex),
+}
+
+impl Search {
+    /// Compile `term` for `mode`. Searching names only, a term starting
+    /// with `/` is a regular expression whatever the mode; searching paths
+    /// too, it is the start of a path. Regular expressions ignore case. In
+    /// regex mode the whole term is the expression, `host:` words included.
+    ///
+    /// # Errors
+    ///
+    /// When the regular expression does not compile; the reason as one
+    /// short line.
+    pub fn new(term: &str, mode: SearchMode, scope: SearchScope) -> Result<Search, String> {
+        let (hosts, term) = match mode {
+            SearchMode::Fuzzy => host_filter(term),
+            SearchMode::Regex => (Vec::new(), term.to_string()),
+        };
+        let hosts = hosts.into_iter().map(String::from).collect();
+        let slash = term
+            .strip_prefix('/')
+            .filter(|_| scope == SearchScope::Name);
+        let pattern = match (slash, mode) {
+            (Some(pattern), _) => pattern,
+            (None, SearchMode::Regex) => &term,
+            (None, SearchMode::Fuzzy) => {
+                return Ok(Search {
+                    hosts,
+                    pattern: Pattern::Query(Query::parse(&term)),
+                })
+            }
+        };
+        let regex = regex::RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| {
+                // Syntax errors repeat the pattern and point at the problem
+                // above a last `error: ...` line; that line is the reason.
+                let message = e.to_string();
+                let reason = message.lines().last().unwrap_or_default();
+                reason.trim_start_matches("error: ").to_string()
+            })?;
+        Ok(Search {
+            hosts,
+            pattern: Pattern::Regex(regex),
+        })
+    }
+
+    #[must_use]
+    pub fn is_regex(&self) -> bool {
+        matches!(self.pattern, Pattern::Regex(_))
+    }
+
+    /// The hosts a project must be checked out from, any of them.
+    #[must_use]
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// The search as it was understood, term by term, for showing.
+    #[must_use]
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms: Vec<String> = match &self.pattern {
+            Pattern::Query(query) => query.terms.iter().map(ToString::to_string).collect(),
+            Pattern::Regex(regex) => vec![format!("regex \"{}\"", regex.as_str())],
+        };
+        terms.extend(self.hosts.iter().map(|host| format!("host \"{host}\"")));
+        terms
+    }
+
+    /// How `name` matches; a regex match has no score and highlights its
+    /// first match.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<fuzzy::Match> {
+        match &self.pattern {
+            Pattern::Query(query) => query.find(name),
+            Pattern::Regex(regex) => {
+                let found = regex.find(name)?;
+                let start = name[..found.start()].chars().count();
+                let len = found.as_str().chars().count();
+                Some(fuzzy::Match {
+                    score: 0,
+                    positions: (start..start + len).collect(),
+                })
+            }
+        }
+    }
+
+    /// How a project matches: by `name`, or else by its `path_text`, the
+    /// text its `SearchScope` searches.
+    #[must_use]
+    pub fn find_project(&self, name: &str, path_text: Option<&str>) -> Option<Found> {
+        match self.find(name) {
+            Some(found) => Some(Found::Name(found)),
+            None => self.find(path_text?).map(Found::Path),
+        }
+    }
 
-    let default_root = &String::from("~");
-    for root in config.get("roots").unwrap_or(default_root).split(':') {
-        cmd.push(root.to_string());
+    /// The names that match, best first; see `fuzzy::rank`. Regex matches
+    /// are ordered by `tie` alone. Hosts are not checked; names carry none.
+    #[must_use]
+    pub fn rank<'a, I, F>(&self, names: I, tie: F) -> Vec<&'a str>
+    where
+        I: IntoIterator<Item = &'a str>,
+        F: Fn(&str, &str) -> std::cmp::Ordering,
+    {
+        fuzzy::rank(names, |name| self.find(name).map(|m| m.score), tie)
     }
 
-    let cmd: Vec<&str> = cmd.iter().map(String::as_ref).collect();
-    f(&cmd, options);
+    /// As `rank`, then the names that only match by the text `path_text`
+    /// gives for them, ranked the same way among themselves.
+    #[must_use]
+    pub fn rank_with_paths<'a, I, P, F>(&self, names: I, path_text: P, tie: F) -> Vec<&'a str>
+    where
+        I: IntoIterator<Item = &'a str>,
+        P: Fn(&str) -> Option<String>,
+        F: Fn(&str, &str) -> std::cmp::Ordering,
+    {
+        let names: Vec<&str> = names.into_iter().collect();
+        let mut ranked = self.rank(names.iter().copied(), &tie);
+        let by_name: BTreeSet<&str> = ranked.iter().copied().collect();
+        ranked.extend(fuzzy::rank(
+            names.into_iter().filter(|name| !by_name.contains(name)),
+            |name| self.find(&path_text(name)?).map(|m| m.score),
+            &tie,
+        ));
+        ranked
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct CoreState {
     pub projects: BTreeMap<String, String>,
     pub search_term: String,
-    pub filtered_projects: BTreeSet<String>,
+    /// The projects matching the search term, best match first.
+    pub filtered_projects: Vec<String>,
     pub current_session: String,
     pub selected_index: Option<usize>,
+    pub search_mode: SearchMode,
+    /// Why the search term is not a valid regular expression; the list
+    /// stays as the last valid term left it.
+    pub search_error: Option<String>,
+    /// Also search projects' paths. There are no roots here, so relative
+    /// paths are full paths.
+    pub search_scope: SearchScope,
 }
 
 impl CoreState {
     #[must_use]
     pub fn init(projects: &BTreeMap<String, String>, current_session: String) -> CoreState {
-        let mut filtered_projects = projects.keys().cloned().collect::<BTreeSet<String>>();
-        filtered_projects.remove(&current_session.clone());
+        let filtered_projects = projects
+            .keys()
+            .filter(|p| **p != current_session)
+            .cloned()
+            .collect();
         CoreState {
             projects: projects.clone(),
             search_term: String::new(),
             filtered_projects,
             current_session,
             selected_index: if projects.is_empty() { None } else { Some(0) },
+            search_mode: SearchMode::default(),
+            search_error: None,
+            search_scope: SearchScope::default(),
         }
     }
 
@@ -63,6 +1236,18 @@ impl CoreState {
         self.update_filtered_projects();
     }
 
+    /// Switch between fuzzy and regular expression matching.
+    pub fn toggle_search_mode(&mut self) {
+        self.search_mode = self.search_mode.toggled();
+        self.update_filtered_projects();
+    }
+
+    /// Move on to the next search scope; see `SearchScope::next`.
+    pub fn cycle_search_scope(&mut self) {
+        self.search_scope = self.search_scope.next();
+        self.update_filtered_projects();
+    }
+
     pub fn up(&mut self) {
         self.selected_index = match self.selected_index {
             Some(0) => Some(0),
@@ -74,7 +1259,10 @@ impl CoreState {
 
     pub fn down(&mut self) {
         self.selected_index = match self.selected_index {
-            Some(index) => Some(min(self.filtered_projects.len() - 1, index + 1)),
+            Some(index) => Some(min(
+                self.filtered_projects.len().saturating_sub(1),
+                index + 1,
+            )),
             None => None,
         };
         self.update_filtered_projects();
@@ -83,7 +1271,7 @@ impl CoreState {
     #[must_use]
     pub fn selected_item(&self) -> Option<String> {
         match self.selected_index {
-            Some(index) => self.filtered_projects.clone().into_iter().nth(index),
+            Some(index) => self.filtered_projects.get(index).cloned(),
             None => None,
         }
     }
@@ -91,16 +1279,31 @@ impl CoreState {
     fn update_filtered_projects(&mut self) {
         let prior_selection = &self.selected_item();
 
-        let regex = self.build_search_regex();
-        self.filtered_projects = self
-            .projects
-            .keys()
-            .filter(|p| !self.current_session.eq(*p))
-            .filter(|p| regex.is_match(p))
-            .fold(BTreeSet::new(), |mut c, v| {
-                c.insert(v.to_owned());
-                c
-            });
+        let search = match Search::new(&self.search_term, self.search_mode, self.search_scope) {
+            Ok(search) => search,
+            Err(error) => {
+                self.search_error = Some(error);
+                return;
+            }
+        };
+        self.search_error = None;
+        let current_session = &self.current_session;
+        let (projects, scope) = (&self.projects, self.search_scope);
+        // There are no roots here to tell a project's host by, so no project
+        // is checked out from the hosts `host:` words ask for.
+        let any_host = search.hosts().is_empty();
+        self.filtered_projects = search
+            .rank_with_paths(
+                self.projects
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|p| *p != current_session && any_host),
+                |name| scope.text(projects.get(name)?, &[]),
+                str::cmp,
+            )
+            .into_iter()
+            .map(String::from)
+            .collect();
 
         // now seek current selection, if present update index
         // otherwise update current selection
@@ -114,26 +1317,11 @@ impl CoreState {
                 })
         {
             self.selected_index = Some(position);
+        } else if let Some(index) = self.selected_index {
+            // The selection was filtered out: stay within the list.
+            self.selected_index = Some(min(index, self.filtered_projects.len().saturating_sub(1)));
         }
     }
-
-    fn build_search_regex(&mut self) -> regex::Regex {
-        let regex_str = self
-            .search_term
-            .chars()
-            .enumerate()
-            .fold(String::new(), |acc, (i, c)| {
-                if i != 0 {
-                    format!("{acc}.*?{c}")
-                } else {
-                    format!("{acc}{c}")
-                }
-            });
-        RegexBuilder::new(&regex_str)
-            .case_insensitive(true)
-            .build()
-            .unwrap()
-    }
 }
 
 #[cfg(test)]
@@ -141,60 +1329,1394 @@ mod test {
     use std::collections::BTreeMap;
 
     use super::*;
+    use crate::source::{FdSource, ProjectSource};
+
+    /// Discovery output as the plugin lists it: read by `FdSource`, outside
+    /// any root, and named by `name_root_projects`.
+    fn named(output: &str, naming: Naming) -> BTreeMap<String, String> {
+        let paths = FdSource::default()
+            .projects("", output)
+            .into_iter()
+            .map(|project| project.path)
+            .collect();
+        name_root_projects(&paths, naming, &[], &BTreeMap::new())
+    }
+
+    /// The command and context `discovery_command` gives for `root` with
+    /// the default markers and nothing excluded.
+    fn command(root: &str, backend: Backend) -> (Vec<String>, BTreeMap<String, String>) {
+        discovery_command(
+            &Root::new(root),
+            backend,
+            &Markers::default(),
+            &Exclude::default(),
+        )
+    }
 
     #[test]
-    fn refresh_projects_returns_expected_options() {
-        let mut opts: Option<BTreeMap<String, String>> = None;
+    fn discovery_command_returns_expected_options() {
+        assert_eq!(
+            command("~", Backend::Fd).1,
+            BTreeMap::from([
+                (String::from("command"), String::from("refresh_projects")),
+                (String::from("backend"), String::from("fd")),
+                (String::from("root"), String::from("~")),
+            ])
+        );
+    }
 
-        refresh_projects(&BTreeMap::new(), |_, context| {
-            opts = Some(context);
-        });
+    #[test]
+    fn discovery_command_base_command() {
         assert_eq!(
-            opts,
-            Some(BTreeMap::from([(
-                String::from("command"),
-                String::from("refresh_projects")
-            )]))
+            command("~", Backend::Fd).0[..6],
+            vec![
+                "fd",
+                "-0",
+                "-Htd",
+                "-tf",
+                "--max-depth=2",
+                "^(\\.git|\\.jj|\\.hg|\\.pijul|\\.fslckout|_FOSSIL_)$"
+            ]
         );
     }
 
     #[test]
-    fn refresh_projects_base_command() {
-        let mut cmd: Vec<String> = Vec::new();
+    fn discovery_command_ends_with_the_root() {
+        assert_eq!(command("~", Backend::Fd).0[6..], vec!["~"]);
+    }
 
-        refresh_projects(&BTreeMap::new(), |c, _| {
-            for item in c {
-                cmd.push((*item).to_string());
-            }
-        });
-        assert_eq!(cmd[..4], vec!["fd", "-Htd", "--max-depth=2", "^\\.git$"]);
+    #[test]
+    fn fd_source_runs_configured_roots_in_order() {
+        let config = BTreeMap::from([(
+            String::from("roots"),
+            String::from("~/personal_projects:~/work_projects"),
+        )]);
+        let commands =
+            FdSource::from_config(&config, &parse_roots(&config), Backend::Fd).commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0[6..], vec!["~/personal_projects"]);
+        assert_eq!(commands[1].0[6..], vec!["~/work_projects"]);
+        let roots: Vec<&str> = commands.iter().map(|(_, c)| c["root"].as_str()).collect();
+        assert_eq!(roots, vec!["~/personal_projects", "~/work_projects"]);
+    }
+
+    #[test]
+    fn discovery_command_with_fdfind() {
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git"),
+        )]));
+        let (cmd, context) = discovery_command(
+            &Root::new("~"),
+            Backend::Fdfind,
+            &markers,
+            &Exclude::default(),
+        );
+        assert_eq!(
+            cmd,
+            vec![
+                "fdfind",
+                "-0",
+                "-Htd",
+                "-tf",
+                "--max-depth=2",
+                "^\\.git$",
+                "~"
+            ]
+        );
+        assert_eq!(context["backend"], "fdfind");
+    }
+
+    #[test]
+    fn discovery_command_with_find() {
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git:.hg"),
+        )]));
+        let (cmd, context) = discovery_command(
+            &Root::new("~/personal_projects"),
+            Backend::Find,
+            &markers,
+            &Exclude::default(),
+        );
+        assert_eq!(context["backend"], "find");
+        assert_eq!(
+            cmd,
+            vec![
+                "find",
+                "~/personal_projects",
+                "-maxdepth",
+                "2",
+                "(",
+                "-name",
+                ".git",
+                "-o",
+                "-name",
+                ".hg",
+                ")",
+                "(",
+                "-type",
+                "d",
+                "-exec",
+                "printf",
+                "%s/\\0",
+                "{}",
+                "+",
+                "-o",
+                "-type",
+                "f",
+                "-print0",
+                ")"
+            ]
+        );
+    }
+
+    fn exclude(globs: &str) -> Exclude {
+        Exclude::from_config(&BTreeMap::from([(
+            String::from("exclude"),
+            String::from(globs),
+        )]))
+    }
+
+    #[test]
+    fn fd_is_passed_exclude_globs() {
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git"),
+        )]));
+        let (cmd, _) = discovery_command(
+            &Root::new("~/src"),
+            Backend::Fd,
+            &markers,
+            &exclude("node_modules:/archive"),
+        );
+        assert_eq!(
+            cmd,
+            vec![
+                "fd",
+                "-0",
+                "-Htd",
+                "-tf",
+                "--max-depth=2",
+                "--exclude",
+                "node_modules",
+                "--exclude",
+                "/archive",
+                "^\\.git$",
+                "~/src"
+            ]
+        );
+    }
+
+    #[test]
+    fn find_prunes_excluded_names() {
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git"),
+        )]));
+        let (cmd, _) = discovery_command(
+            &Root::new("~/src"),
+            Backend::Find,
+            &markers,
+            &exclude("node_modules:*.bak:/archive"),
+        );
+        assert_eq!(
+            cmd[..17],
+            vec![
+                "find",
+                "~/src",
+                "-mindepth",
+                "1",
+                "-maxdepth",
+                "2",
+                "(",
+                "-name",
+                "node_modules",
+                "-o",
+                "-name",
+                "*.bak",
+                ")",
+                "-prune",
+                "-o",
+                "(",
+                "-name",
+            ]
+        );
+    }
+
+    #[test]
+    fn root_options_change_the_command() {
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git"),
+        )]));
+        let root = Root::parse("~/src[depth=3,follow]");
+        let (fd, _) = discovery_command(&root, Backend::Fd, &markers, &Exclude::default());
+        assert_eq!(
+            fd,
+            vec![
+                "fd",
+                "-0",
+                "-Htd",
+                "-tf",
+                "--max-depth=4",
+                "--follow",
+                "^\\.git$",
+                "~/src"
+            ]
+        );
+        let (find, context) =
+            discovery_command(&root, Backend::Find, &markers, &Exclude::default());
+        assert_eq!(find[..5], vec!["find", "-L", "~/src", "-maxdepth", "4"]);
+        assert_eq!(context["root"], "~/src");
+    }
+
+    #[test]
+    fn ghq_roots_search_exactly_three_levels_down() {
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git"),
+        )]));
+        let root = Root::parse("~/ghq[layout=ghq,depth=1]");
+        assert_eq!(root.depth, GHQ_DEPTH);
+        assert_eq!(root.min_depth(), Some(4));
+        assert_eq!(Root::new("~/ghq").min_depth(), None);
+        let (fd, _) = discovery_command(&root, Backend::Fd, &markers, &Exclude::default());
+        assert_eq!(
+            fd,
+            vec![
+                "fd",
+                "-0",
+                "-Htd",
+                "-tf",
+                "--min-depth=4",
+                "--max-depth=4",
+                "^\\.git$",
+                "~/ghq"
+            ]
+        );
+        let (find, _) = discovery_command(&root, Backend::Find, &markers, &Exclude::default());
+        assert_eq!(
+            find[..6],
+            vec!["find", "~/ghq", "-mindepth", "4", "-maxdepth", "4"]
+        );
+    }
+
+    #[test]
+    fn ghq_roots_know_each_project_host() {
+        let root = Root::parse("/ghq/[layout=ghq]");
+        assert_eq!(root.host("/ghq/github.com/acme/app"), Some("github.com"));
+        assert_eq!(root.host("/ghq/github.com/acme"), None);
+        assert_eq!(root.host("/ghqs/github.com/acme/app"), None);
+        assert_eq!(Root::new("/ghq").host("/ghq/github.com/acme/app"), None);
+    }
+
+    #[test]
+    fn ghq_projects_are_named_org_repo() {
+        let roots = vec![Root::parse("/ghq[layout=ghq]"), Root::new("/src")];
+        let paths = BTreeSet::from([
+            String::from("/ghq/github.com/acme/app"),
+            String::from("/ghq/github.com/acme/site"),
+            String::from("/ghq/gitlab.com/acme/site"),
+            String::from("/src/app"),
+        ]);
+        assert_eq!(
+            name_root_projects(&paths, Naming::Shortest, &roots, &BTreeMap::new()),
+            BTreeMap::from([
+                (
+                    String::from("acme/app"),
+                    String::from("/ghq/github.com/acme/app")
+                ),
+                (
+                    String::from("github.com/acme/site"),
+                    String::from("/ghq/github.com/acme/site")
+                ),
+                (
+                    String::from("gitlab.com/acme/site"),
+                    String::from("/ghq/gitlab.com/acme/site")
+                ),
+                (String::from("app"), String::from("/src/app")),
+            ])
+        );
+    }
+
+    #[test]
+    fn host_words_are_taken_out_of_the_search_term() {
+        assert_eq!(host_filter("api"), (vec![], String::from("api")));
+        assert_eq!(
+            host_filter("host:github api host:gitlab.com"),
+            (vec!["github", "gitlab.com"], String::from("api"))
+        );
+        assert_eq!(host_filter("host:"), (vec![], String::new()));
+    }
+
+    #[test]
+    fn plain_roots_get_default_options() {
+        let config = BTreeMap::from([(String::from("roots"), String::from("~/a:/b/c/"))]);
+        assert_eq!(
+            parse_roots(&config),
+            vec![Root::new("~/a"), Root::new("/b/c/")]
+        );
+        assert_eq!(
+            roots(&config),
+            vec![
+                "~/a[depth=1,follow=false,hidden=true,prefix=,layout=plain]",
+                "/b/c/[depth=1,follow=false,hidden=true,prefix=,layout=plain]"
+            ]
+        );
+        assert_eq!(parse_roots(&BTreeMap::new()), vec![Root::new("~")]);
     }
 
     #[test]
-    fn refresh_projects_with_default_root() {
-        let mut cmd: Vec<String> = Vec::new();
+    fn roots_record_their_options() {
+        let root = Root::parse("~/src[depth=3,follow,hidden=false,prefix=gh/]");
+        assert_eq!(Root::parse(&root.to_string()), root);
+        let ghq = Root::parse("~/ghq[layout=ghq]");
+        assert_eq!(Root::parse(&ghq.to_string()), ghq);
+        let config = |roots: &str| BTreeMap::from([(String::from("roots"), String::from(roots))]);
+        assert_ne!(roots(&config("~/src")), roots(&config("~/src[depth=3]")));
+        assert_ne!(roots(&config("~/src")), roots(&config("~/src[prefix=s/]")));
+        assert_eq!(roots(&config("~/src")), roots(&config("~/src[depth=1]")));
+    }
 
-        refresh_projects(&BTreeMap::new(), |c, _| {
-            for item in c {
-                cmd.push((*item).to_string());
+    #[test]
+    fn colons_inside_options_do_not_split_roots() {
+        let config =
+            BTreeMap::from([(String::from("roots"), String::from("/work[prefix=w:]:/src"))]);
+        assert_eq!(
+            parse_roots(&config),
+            vec![
+                Root {
+                    prefix: String::from("w:"),
+                    ..Root::new("/work")
+                },
+                Root::new("/src"),
+            ]
+        );
+    }
+
+    #[test]
+    fn root_options_are_parsed() {
+        assert_eq!(
+            Root::parse("~/src/github.com[depth=3, follow, hidden=false, prefix=gh/]"),
+            Root {
+                path: String::from("~/src/github.com"),
+                depth: 3,
+                follow: true,
+                hidden: false,
+                prefix: String::from("gh/"),
+                layout: RootLayout::Plain,
             }
-        });
-        assert_eq!(cmd[4..], vec!["~"]);
+        );
+        assert_eq!(
+            Root::parse("~/src[follow=true,hidden]"),
+            Root {
+                follow: true,
+                ..Root::new("~/src")
+            }
+        );
     }
 
     #[test]
-    fn refresh_projects_with_configured_roots() {
-        let mut cmd: Vec<String> = Vec::new();
+    fn invalid_root_options_are_ignored() {
+        assert_eq!(
+            Root::parse("~/src[depth=deep,follow=maybe,colour=red,prefix]"),
+            Root::new("~/src")
+        );
+        assert_eq!(Root::parse("~/src[]"), Root::new("~/src"));
+    }
 
+    #[test]
+    fn brackets_inside_a_path_are_not_options() {
+        assert_eq!(Root::parse("~/[old]/src"), Root::new("~/[old]/src"));
+        assert_eq!(Root::parse("~/src]"), Root::new("~/src]"));
+    }
+
+    #[test]
+    fn mixed_plain_and_optioned_roots() {
         let config = BTreeMap::from([(
             String::from("roots"),
-            String::from("~/personal_projects:~/work_projects"),
+            String::from("~/flat[depth=1]:~/deep[depth=4]:~/plain"),
         )]);
-        refresh_projects(&config, |c, _| {
-            for item in c {
-                cmd.push((*item).to_string());
-            }
-        });
-        assert_eq!(cmd[4..], vec!["~/personal_projects", "~/work_projects"]);
+        let parsed = parse_roots(&config);
+        assert_eq!(
+            parsed.iter().map(|r| r.depth).collect::<Vec<_>>(),
+            vec![1, 4, DEFAULT_DEPTH]
+        );
+        assert_eq!(parsed[1].max_depth(), 5);
+    }
+
+    #[test]
+    fn root_contains_only_whole_components() {
+        let root = Root::new("/src/");
+        assert!(root.contains("/src/api"));
+        assert!(root.contains("/src"));
+        assert!(!root.contains("/srcs/api"));
+    }
+
+    #[test]
+    fn root_without_hidden_drops_hidden_directories() {
+        let output = "/src/api/.git/\0/src/.config/nvim/.git/\0/src/a/.b/.hg/\0/src/.git/\0";
+        assert_eq!(Root::new("/src").filter_output(output), output);
+        assert_eq!(
+            Root::parse("/src[hidden=false]").filter_output(output),
+            "/src/api/.git/\0/src/.git/\0"
+        );
+    }
+
+    #[test]
+    fn prefixed_roots_name_their_projects_apart() {
+        let roots = vec![
+            Root::parse("/work[prefix=w-]"),
+            Root::new("/personal"),
+            Root::new("/other"),
+        ];
+        let paths: BTreeSet<String> = ["/work/api", "/personal/api", "/other/api", "/work/web"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            name_root_projects(&paths, Naming::Shortest, &roots, &BTreeMap::new()),
+            BTreeMap::from([
+                (String::from("other/api"), String::from("/other/api")),
+                (String::from("personal/api"), String::from("/personal/api")),
+                (String::from("w-api"), String::from("/work/api")),
+                (String::from("w-web"), String::from("/work/web")),
+            ])
+        );
+    }
+
+    fn env() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (String::from("HOME"), String::from("/home/me")),
+            (String::from("WORK"), String::from("/work")),
+            (String::from("ORG_1"), String::from("acme")),
+        ])
+    }
+
+    #[test]
+    fn expand_path_resolves_home() {
+        assert_eq!(expand_path("~", &env()).unwrap(), "/home/me");
+        assert_eq!(expand_path("~/src", &env()).unwrap(), "/home/me/src");
+        assert_eq!(expand_path("/src/~", &env()).unwrap(), "/src/~");
+        assert_eq!(expand_path("~other/src", &env()).unwrap(), "~other/src");
+    }
+
+    #[test]
+    fn expand_path_resolves_variables() {
+        assert_eq!(expand_path("$WORK/src", &env()).unwrap(), "/work/src");
+        assert_eq!(
+            expand_path("${WORK}/$ORG_1/${ORG_1}x", &env()).unwrap(),
+            "/work/acme/acmex"
+        );
+        assert_eq!(expand_path("$HOME-old", &env()).unwrap(), "/home/me-old");
+        assert_eq!(expand_path("/plain/path", &env()).unwrap(), "/plain/path");
+    }
+
+    #[test]
+    fn expand_path_keeps_a_lone_dollar() {
+        assert_eq!(expand_path("/a/$/b$", &env()).unwrap(), "/a/$/b$");
+        assert_eq!(expand_path("/a/$1", &env()).unwrap(), "/a/$1");
+    }
+
+    #[test]
+    fn expand_path_reports_unset_variables() {
+        assert_eq!(
+            expand_path("$PROJECTS/src", &env()),
+            Err(ExpandError::Unset {
+                path: String::from("$PROJECTS/src"),
+                variable: String::from("PROJECTS"),
+            })
+        );
+        assert_eq!(
+            expand_path("~/src", &BTreeMap::new())
+                .unwrap_err()
+                .to_string(),
+            "cannot expand `~/src`: `HOME` is not set"
+        );
+        assert_eq!(
+            expand_path("${WORK/src", &env()).unwrap_err().to_string(),
+            "cannot expand `${WORK/src`: `${` is not closed"
+        );
+    }
+
+    #[test]
+    fn parse_env_reads_name_value_lines() {
+        assert_eq!(
+            parse_env("HOME=/home/me\nEMPTY=\nEQ=a=b\ncontinued line\n=x\n1BAD=y\n"),
+            BTreeMap::from([
+                (String::from("EMPTY"), String::new()),
+                (String::from("EQ"), String::from("a=b")),
+                (String::from("HOME"), String::from("/home/me")),
+            ])
+        );
+    }
+
+    #[test]
+    fn layout_paths_are_told_from_built_in_layouts() {
+        assert!(!is_layout_path("default"));
+        assert!(!is_layout_path("compact"));
+        assert!(is_layout_path("~/layouts/dev.kdl"));
+        assert!(is_layout_path("$LAYOUTS/dev"));
+        assert!(is_layout_path("dev.kdl"));
+        assert!(is_layout_path("dev.KDL"));
+        assert!(!is_layout_path("kdl"));
+    }
+
+    #[test]
+    fn pinned_projects_defaults_to_none() {
+        assert!(pinned_projects(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn pinned_projects_parses_name_path_pairs() {
+        let config = BTreeMap::from([(
+            String::from("pinned"),
+            String::from("home=~; dotfiles = ~/.dotfiles ;"),
+        )]);
+        assert_eq!(
+            pinned_projects(&config),
+            BTreeMap::from([
+                (String::from("dotfiles"), String::from("~/.dotfiles")),
+                (String::from("home"), String::from("~")),
+            ])
+        );
+    }
+
+    #[test]
+    fn pinned_projects_skips_malformed_entries() {
+        let config = BTreeMap::from([(
+            String::from("pinned"),
+            String::from("no-equals;=~/nameless;pathless=;ok=/ok=really"),
+        )]);
+        assert_eq!(
+            pinned_projects(&config),
+            BTreeMap::from([(String::from("ok"), String::from("/ok=really"))])
+        );
+    }
+
+    #[test]
+    fn discovery_output_names_projects_by_directory() {
+        assert_eq!(
+            named(
+                "/src/alpha/.git/\n/src/beta/.git/\nnot a repo\n",
+                Naming::Shortest
+            ),
+            BTreeMap::from([
+                (String::from("alpha"), String::from("/src/alpha")),
+                (String::from("beta"), String::from("/src/beta")),
+            ])
+        );
+    }
+
+    #[test]
+    fn discovery_output_disambiguates_colliding_names() {
+        assert_eq!(
+            named(
+                "/home/me/work/api/.git/\n/home/me/personal/api/.git/\n/home/me/work/web/.git/\n",
+                Naming::Shortest
+            ),
+            BTreeMap::from([
+                (
+                    String::from("personal/api"),
+                    String::from("/home/me/personal/api")
+                ),
+                (String::from("web"), String::from("/home/me/work/web")),
+                (String::from("work/api"), String::from("/home/me/work/api")),
+            ])
+        );
+    }
+
+    #[test]
+    fn discovery_output_lengthens_suffix_until_unique() {
+        assert_eq!(
+            named(
+                "/a/work/api/.git/\n/b/work/api/.git/\n/b/play/api/.git/\n",
+                Naming::Shortest
+            ),
+            BTreeMap::from([
+                (String::from("a/work/api"), String::from("/a/work/api")),
+                (String::from("b/work/api"), String::from("/b/work/api")),
+                (String::from("play/api"), String::from("/b/play/api")),
+            ])
+        );
+    }
+
+    #[test]
+    fn discovery_output_handles_a_path_that_is_a_suffix_of_another() {
+        assert_eq!(
+            named("/api/.git/\n/x/api/.git/\n", Naming::Shortest),
+            BTreeMap::from([
+                (String::from("api"), String::from("/api")),
+                (String::from("x/api"), String::from("/x/api")),
+            ])
+        );
+    }
+
+    #[test]
+    fn discovery_output_with_duplicate_lines_lists_the_project_once() {
+        assert_eq!(
+            named("/src/alpha/.git/\n/src/alpha/.git/\n", Naming::Shortest),
+            BTreeMap::from([(String::from("alpha"), String::from("/src/alpha"))])
+        );
+    }
+
+    #[test]
+    fn discovery_output_with_differently_spelt_duplicates_lists_the_project_once() {
+        assert_eq!(
+            named(
+                "/src/alpha/.git/\n/src//alpha/.git/\n/src/./alpha/.git/\n/src/x/../alpha/.git/\n",
+                Naming::Shortest
+            ),
+            BTreeMap::from([(String::from("alpha"), String::from("/src/alpha"))])
+        );
+    }
+
+    #[test]
+    fn discovery_output_with_duplicates_among_other_projects() {
+        assert_eq!(
+            named(
+                "/src/work/api/.git/\n/src/beta/.git/\n/src/work/api/.git/\n/src/personal/api/.git/\n/src/beta/.git/\n",
+                Naming::Shortest
+            ),
+            BTreeMap::from([
+                (
+                    String::from("personal/api"),
+                    String::from("/src/personal/api")
+                ),
+                (String::from("beta"), String::from("/src/beta")),
+                (String::from("work/api"), String::from("/src/work/api")),
+            ])
+        );
+    }
+
+    #[test]
+    fn discovery_output_with_only_duplicates_of_a_colliding_name() {
+        // A duplicate must not count as a second repository named `api`.
+        assert_eq!(
+            named(
+                "/src/work/api/.git/\n/src/work/api/.git/\n",
+                Naming::Shortest
+            ),
+            BTreeMap::from([(String::from("api"), String::from("/src/work/api"))])
+        );
+    }
+
+    #[test]
+    fn normalise_path_is_lexical() {
+        assert_eq!(normalise_path("/src//a/./b/"), "/src/a/b");
+        assert_eq!(normalise_path("/src/a/../b"), "/src/b");
+        assert_eq!(normalise_path("/../a"), "/a");
+        assert_eq!(normalise_path("/"), "/");
+        assert_eq!(normalise_path("~/src/"), "~/src");
+        assert_eq!(normalise_path("../a/../../b"), "../../b");
+        assert_eq!(normalise_path("./"), ".");
+    }
+
+    #[test]
+    fn resolve_command_passes_paths_as_arguments() {
+        let cmd = resolve_command(&[String::from("/src/a b"), String::from("/src/c")]);
+        assert_eq!(cmd[..2], vec!["sh", "-c"]);
+        assert_eq!(cmd[3..], vec!["sh", "/src/a b", "/src/c"]);
+    }
+
+    #[test]
+    fn parse_resolved_reads_path_pairs() {
+        assert_eq!(
+            parse_resolved("/src/link\t/real/api/\n/src/api\t/src/api\ngarbage\n/x\trelative\n"),
+            BTreeMap::from([
+                (String::from("/src/api"), String::from("/src/api")),
+                (String::from("/src/link"), String::from("/real/api")),
+            ])
+        );
+    }
+
+    #[test]
+    fn dedupe_keeps_one_path_per_real_location() {
+        let paths: BTreeSet<String> = ["/home/me/src/api", "/src/api", "/src/link", "/src/web"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let real = BTreeMap::from([
+            (String::from("/home/me/src/api"), String::from("/src/api")),
+            (String::from("/src/api"), String::from("/src/api")),
+            (String::from("/src/link"), String::from("/src/web")),
+        ]);
+        assert_eq!(
+            dedupe_by_real_path(&paths, &real),
+            BTreeSet::from([String::from("/src/api"), String::from("/src/web")])
+        );
+    }
+
+    #[test]
+    fn dedupe_prefers_the_shortest_alias() {
+        let paths: BTreeSet<String> = ["/a/long/link", "/b/link"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let real = BTreeMap::from([
+            (String::from("/a/long/link"), String::from("/real")),
+            (String::from("/b/link"), String::from("/real")),
+        ]);
+        assert_eq!(
+            dedupe_by_real_path(&paths, &real),
+            BTreeSet::from([String::from("/b/link")])
+        );
+    }
+
+    #[test]
+    fn overlapping_roots_are_detected() {
+        let roots: Vec<Root> = [
+            "/home/me/src",
+            "/home/me/src/work/",
+            "/other",
+            "/home/me/srcs",
+            "/other",
+        ]
+        .iter()
+        .map(|p| Root::new(p))
+        .collect();
+        assert_eq!(
+            overlapping_roots(&roots),
+            vec![
+                (
+                    String::from("/home/me/src"),
+                    String::from("/home/me/src/work/")
+                ),
+                (String::from("/other"), String::from("/other")),
+            ]
+        );
+        assert!(overlapping_roots(&[Root::new("/a"), Root::new("/b")]).is_empty());
+    }
+
+    #[test]
+    fn discovery_output_with_parent_naming() {
+        assert_eq!(
+            named(
+                "/src/work/api/.git/\n/src/personal/api/.git/\n/src/web/.git/\n",
+                Naming::Parent
+            ),
+            BTreeMap::from([
+                (
+                    String::from("personal/api"),
+                    String::from("/src/personal/api")
+                ),
+                (String::from("src/web"), String::from("/src/web")),
+                (String::from("work/api"), String::from("/src/work/api")),
+            ])
+        );
+    }
+
+    #[test]
+    fn discovery_output_with_path_naming() {
+        assert_eq!(
+            named("/src/work/api/.git/\n", Naming::Path),
+            BTreeMap::from([(String::from("/src/work/api"), String::from("/src/work/api"))])
+        );
+    }
+
+    #[test]
+    fn naming_reads_config() {
+        let config = |v: &str| BTreeMap::from([(String::from("naming"), String::from(v))]);
+        assert_eq!(Naming::from_config(&BTreeMap::new()), Naming::Shortest);
+        assert_eq!(Naming::from_config(&config("parent")), Naming::Parent);
+        assert_eq!(Naming::from_config(&config("path")), Naming::Path);
+        assert_eq!(Naming::from_config(&config("remote")), Naming::Remote);
+        assert_eq!(Naming::from_config(&config("bogus")), Naming::Shortest);
+    }
+
+    fn remotes(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(path, name)| ((*path).to_string(), (*name).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn remote_naming_uses_org_and_repo() {
+        let paths = BTreeSet::from([
+            String::from("/src/app"),
+            String::from("/work/backend"),
+            String::from("/src/notes"),
+        ]);
+        let remotes = remotes(&[
+            ("/src/app", "acme/storefront"),
+            ("/work/backend", "acme/billing"),
+        ]);
+        assert_eq!(
+            name_root_projects(&paths, Naming::Remote, &[], &remotes),
+            BTreeMap::from([
+                (String::from("acme/billing"), String::from("/work/backend")),
+                (String::from("acme/storefront"), String::from("/src/app")),
+                (String::from("notes"), String::from("/src/notes")),
+            ])
+        );
+        assert_eq!(
+            name_root_projects(&paths, Naming::Shortest, &[], &remotes)["app"],
+            "/src/app"
+        );
+    }
+
+    #[test]
+    fn shared_remotes_fall_back_to_directory_names() {
+        let paths = BTreeSet::from([
+            String::from("/src/app"),
+            String::from("/scratch/app"),
+            String::from("/src/acme/site"),
+            String::from("/x/site"),
+            String::from("/src/web"),
+        ]);
+        let remotes = remotes(&[
+            ("/src/app", "acme/app"),
+            ("/scratch/app", "acme/app"),
+            ("/src/web", "acme/site"),
+        ]);
+        assert_eq!(
+            name_root_projects(&paths, Naming::Remote, &[], &remotes),
+            BTreeMap::from([
+                (String::from("scratch/app"), String::from("/scratch/app")),
+                (String::from("src/app"), String::from("/src/app")),
+                (String::from("acme/site"), String::from("/src/acme/site")),
+                (String::from("x/site"), String::from("/x/site")),
+                (String::from("web"), String::from("/src/web")),
+            ])
+        );
+    }
+
+    #[test]
+    fn remote_names_keep_root_prefixes() {
+        let paths = BTreeSet::from([String::from("/gh/app")]);
+        assert_eq!(
+            name_root_projects(
+                &paths,
+                Naming::Remote,
+                &[Root::parse("/gh[prefix=gh/]")],
+                &remotes(&[("/gh/app", "acme/app")])
+            ),
+            BTreeMap::from([(String::from("gh/acme/app"), String::from("/gh/app"))])
+        );
+    }
+
+    #[test]
+    fn session_names_have_no_slashes() {
+        assert_eq!(session_name("work/api"), "work%2Fapi");
+        assert_eq!(session_name("api"), "api");
+        assert_eq!(session_name("my-api"), "my-api");
+    }
+
+    #[test]
+    fn session_names_do_not_collide() {
+        let names = [
+            "work/api", "work-api", "acme/app", "acme-app", "a%2Fb", "a/b", "a%b", "a%25b",
+        ];
+        let sessions: BTreeSet<String> = names.iter().map(|name| session_name(name)).collect();
+        assert_eq!(sessions.len(), names.len());
+    }
+
+    #[test]
+    fn parse_worktree_lines_keeps_only_git_files() {
+        assert_eq!(
+            parse_worktree_lines("/src/api/.git/\n/src/api-feature/.git\n/.git\n"),
+            vec!["/src/api-feature"]
+        );
+        assert_eq!(
+            parse_lines("/src/api-feature/.git\n", &Markers::default()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn parse_lines_identifies_the_vcs() {
+        assert_eq!(
+            parse_lines(
+                "/src/a/.git/\n/src/b/.jj/\n/src/c/.hg/\n/src/d/.pijul/\n/src/e/.fslckout\n/src/f/_FOSSIL_\n",
+                &Markers::default()
+            ),
+            vec![
+                (String::from("/src/a"), Some(Vcs::Git)),
+                (String::from("/src/b"), Some(Vcs::Jujutsu)),
+                (String::from("/src/c"), Some(Vcs::Mercurial)),
+                (String::from("/src/d"), Some(Vcs::Pijul)),
+                (String::from("/src/e"), Some(Vcs::Fossil)),
+                (String::from("/src/f"), Some(Vcs::Fossil)),
+            ]
+        );
+        assert_eq!(
+            parse_lines("/src/g/.svn/\n/.jj/\nnot a repo\n", &Markers::default()),
+            vec![]
+        );
+    }
+
+    /// Discovery output as the tools print it: what each shape is, the
+    /// output, and the projects and worktrees (or fd 8 repositories, which
+    /// look the same) in it.
+    const OUTPUT_SHAPES: &[(&str, &str, &[&str], &[&str])] = &[
+        ("empty", "", &[], &[]),
+        ("only separators", "\0\0\n", &[], &[]),
+        (
+            "fd -0",
+            "/src/api/.git/\0/src/wt/.git\0",
+            &["/src/api"],
+            &["/src/wt"],
+        ),
+        (
+            "find -print0",
+            "/src/api/.git/\0/src/wt/.git\0/src/hg/.hg/\0",
+            &["/src/api", "/src/hg"],
+            &["/src/wt"],
+        ),
+        (
+            "fd 9 lines",
+            "/src/api/.git/\n/src/wt/.git\n",
+            &["/src/api"],
+            &["/src/wt"],
+        ),
+        (
+            "fd 8 lines without slashes",
+            "/src/api/.git\n",
+            &[],
+            &["/src/api"],
+        ),
+        (
+            "find lines without slashes",
+            "/src/api/.hg\n/src/wt/.git\n",
+            &["/src/api"],
+            &["/src/wt"],
+        ),
+        ("no final separator", "/src/api/.git/", &["/src/api"], &[]),
+        (
+            "crlf",
+            "/src/api/.git/\r\n/src/wt/.git\r\n",
+            &["/src/api"],
+            &["/src/wt"],
+        ),
+        (
+            "blank lines and trailing blanks",
+            "\n/src/api/.git/  \n\n\t\n",
+            &["/src/api"],
+            &[],
+        ),
+        (
+            "newline in a path",
+            "/src/two\nlines/.git/\0/src/wt\n/.git\0",
+            &["/src/two\nlines"],
+            &["/src/wt\n"],
+        ),
+        (
+            "spaces, tabs and unicode",
+            "/src/my project/.git/\0/src/a\tb/.git/\0/src/café/.git/\0/src/api /.git/\0",
+            &["/src/my project", "/src/a\tb", "/src/café", "/src/api "],
+            &[],
+        ),
+        (
+            "repeated slashes",
+            "/src//api//.git//\0/src///wt//.git\0",
+            &["/src/api"],
+            &["/src/wt"],
+        ),
+        (
+            "relative root",
+            "./api/.git/\n./wt/.git\n",
+            &["api"],
+            &["wt"],
+        ),
+        (
+            "marker at the filesystem root",
+            "/.git/\n//.git\n",
+            &[],
+            &[],
+        ),
+        (
+            "entries that are not markers",
+            "/src/api/.svn/\0/src/api/.gitignore\0not a path\0",
+            &[],
+            &[],
+        ),
+    ];
+
+    #[test]
+    fn parses_real_world_output_shapes() {
+        for (shape, output, projects, worktrees) in OUTPUT_SHAPES {
+            let found: Vec<String> = parse_lines(output, &Markers::default())
+                .into_iter()
+                .map(|(p, _)| p)
+                .collect();
+            assert_eq!(found, *projects, "projects in {shape}");
+            assert_eq!(
+                parse_worktree_lines(output),
+                *worktrees,
+                "worktrees in {shape}"
+            );
+        }
+    }
+
+    #[test]
+    fn split_output_detects_the_separator() {
+        assert_eq!(split_output("a\nb\0c\0"), vec!["a\nb", "c"]);
+        assert_eq!(split_output("a \r\nb\n"), vec!["a", "b"]);
+        assert_eq!(split_output("a \0"), vec!["a "]);
+    }
+
+    #[test]
+    fn joined_output_splits_back() {
+        let entries = vec!["/src/a b/.git/", "/src/c\nd/.git"];
+        assert_eq!(split_output(&join_output(entries.clone())), entries);
+        assert_eq!(join_output(Vec::new()), "");
+    }
+
+    fn markers(names: &str, nested: bool) -> Markers {
+        Markers::from_config(&BTreeMap::from([
+            (String::from("markers"), String::from(names)),
+            (String::from("nested_markers"), nested.to_string()),
+        ]))
+    }
+
+    #[test]
+    fn parse_lines_finds_marker_file_projects() {
+        assert_eq!(
+            parse_lines(
+                "/src/app/.git/\n/src/app/Cargo.toml\n/src/scratch/flake.nix\n/src/web/package.json\n",
+                &markers(".git:Cargo.toml:flake.nix", false)
+            ),
+            vec![
+                (String::from("/src/app"), Some(Vcs::Git)),
+                (String::from("/src/app"), None),
+                (String::from("/src/scratch"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_lines_lists_a_workspace_once() {
+        let lines = "/src/ws/Cargo.toml\n/src/ws/core/Cargo.toml\n/src/ws/cli/Cargo.toml\n/src/other/Cargo.toml\n";
+        assert_eq!(
+            parse_lines(lines, &markers("Cargo.toml", false)),
+            vec![
+                (String::from("/src/ws"), None),
+                (String::from("/src/other"), None),
+            ]
+        );
+        assert_eq!(parse_lines(lines, &markers("Cargo.toml", true)).len(), 4);
+    }
+
+    #[test]
+    fn parse_lines_keeps_nested_repositories() {
+        assert_eq!(
+            parse_lines(
+                "/src/app/.git/\n/src/app/vendor/lib/.git/\n/src/app/web/package.json\n",
+                &markers(".git:package.json", false)
+            ),
+            vec![
+                (String::from("/src/app"), Some(Vcs::Git)),
+                (String::from("/src/app/vendor/lib"), Some(Vcs::Git)),
+            ]
+        );
+    }
+
+    #[test]
+    fn discovery_output_lists_a_colocated_repository_once() {
+        assert_eq!(
+            named("/src/api/.git/\n/src/api/.jj/\n", Naming::Shortest),
+            BTreeMap::from([(String::from("api"), String::from("/src/api"))])
+        );
+    }
+
+    #[test]
+    fn search_terms_are_literal_unless_regex() {
+        for term in ["(", "[a", "c++", "\\", "a|b", "*"] {
+            let search = Search::new(term, SearchMode::Fuzzy, SearchScope::Name).unwrap();
+            assert!(!search.is_regex());
+            assert!(search.find(&format!("x{term}y")).is_some(), "{}", term);
+        }
+        assert!(Search::new("c++", SearchMode::Fuzzy, SearchScope::Name)
+            .unwrap()
+            .find("cpp")
+            .is_none());
+    }
+
+    #[test]
+    fn regex_search_by_mode_or_slash() {
+        let search = Search::new("^API-\\w+$", SearchMode::Regex, SearchScope::Name).unwrap();
+        assert!(search.is_regex());
+        assert_eq!(
+            search.find("api-gateway").unwrap().positions,
+            (0..11).collect::<Vec<_>>()
+        );
+        assert!(search.find("rapid-api").is_none());
+        let slash = Search::new("/é.$", SearchMode::Fuzzy, SearchScope::Name).unwrap();
+        assert!(slash.is_regex());
+        assert_eq!(slash.find("caféx").unwrap().positions, vec![3, 4]);
+        assert_eq!(
+            slash.rank(["zé1", "é2", "x"].iter().copied(), str::cmp),
+            vec!["zé1", "é2"]
+        );
+        // Searching paths, a leading `/` is where a full path starts.
+        let path = Search::new("/home/me", SearchMode::Fuzzy, SearchScope::FullPath).unwrap();
+        assert!(!path.is_regex());
+        assert!(Search::new("/a[", SearchMode::Fuzzy, SearchScope::RelativePath).is_ok());
+    }
+
+    #[test]
+    fn invalid_regex_is_a_short_error() {
+        assert_eq!(
+            Search::new("(api", SearchMode::Regex, SearchScope::Name).unwrap_err(),
+            "unclosed group"
+        );
+        assert!(Search::new("/a[", SearchMode::Fuzzy, SearchScope::Name).is_err());
+        assert!(Search::new("a[", SearchMode::Fuzzy, SearchScope::Name).is_ok());
+    }
+
+    fn term(kind: TermKind, text: &str, negated: bool) -> QueryTerm {
+        QueryTerm {
+            kind,
+            text: text.to_string(),
+            negated,
+        }
+    }
+
+    #[test]
+    fn query_parses_each_fzf_term_form() {
+        use Anchor::*;
+        assert_eq!(
+            Query::parse("api 'web ^go rs$ ^main$ !tmp !^old !.bak$ !'x").terms,
+            vec![
+                term(TermKind::Fuzzy, "api", false),
+                term(TermKind::Exact(Anywhere), "web", false),
+                term(TermKind::Exact(Start), "go", false),
+                term(TermKind::Exact(End), "rs", false),
+                term(TermKind::Exact(Whole), "main", false),
+                term(TermKind::Exact(Anywhere), "tmp", true),
+                term(TermKind::Exact(Start), "old", true),
+                term(TermKind::Exact(End), ".bak", true),
+                term(TermKind::Exact(Anywhere), "x", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_skips_empty_terms_and_keeps_escaped_spaces() {
+        assert_eq!(Query::parse("").terms, vec![]);
+        assert_eq!(Query::parse("  ! ^ ' $ ^$ !'").terms, vec![]);
+        assert_eq!(
+            Query::parse(r"my\ project  x\y").terms,
+            vec![
+                term(TermKind::Fuzzy, "my project", false),
+                term(TermKind::Fuzzy, r"x\y", false),
+            ]
+        );
+        // Markers only count where fzf reads them.
+        assert_eq!(
+            Query::parse("a'b a^b $a").terms,
+            vec![
+                term(TermKind::Fuzzy, "a'b", false),
+                term(TermKind::Fuzzy, "a^b", false),
+                term(TermKind::Fuzzy, "$a", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_terms_must_all_match() {
+        let query = Query::parse("api ^my");
+        assert!(query.find("my-api").is_some());
+        assert!(query.find("api").is_none());
+        assert!(query.find("my-web").is_none());
+        assert!(Query::parse("").find("anything").is_some());
+    }
+
+    #[test]
+    fn query_exact_and_anchored_terms() {
+        assert!(Query::parse("'pi-g").find("api-gateway").is_some());
+        assert!(Query::parse("'pig").find("api-gateway").is_none());
+        assert!(Query::parse("pig").find("api-gateway").is_some());
+        assert!(Query::parse("^API").find("api-gateway").is_some());
+        assert!(Query::parse("^gate").find("api-gateway").is_none());
+        assert!(Query::parse("way$").find("api-gateway").is_some());
+        assert!(Query::parse("api$").find("api-gateway").is_none());
+        assert!(Query::parse("^api$").find("api").is_some());
+        assert!(Query::parse("^api$").find("api-gateway").is_none());
+    }
+
+    #[test]
+    fn query_negations_exclude() {
+        let query = Query::parse("api !test !^old !-v1$");
+        assert!(query.find("api").is_some());
+        assert!(query.find("api-tests").is_none());
+        assert!(query.find("old-api").is_none());
+        assert!(query.find("api-v1").is_none());
+        assert!(query.find("api-v12").is_some());
+        // A negated plain term is exact: `!tst` does not exclude `test`.
+        assert!(Query::parse("!tst").find("test").is_some());
+        // Only negations: everything else matches, unranked.
+        assert_eq!(Query::parse("!x").find("abc").unwrap().score, 0);
+    }
+
+    #[test]
+    fn query_positions_and_scores_add_up() {
+        let m = Query::parse("^ap way$").find("api-gateway").unwrap();
+        assert_eq!(m.positions, vec![0, 1, 8, 9, 10]);
+        let ap = fuzzy::find_exact("ap", "api-gateway", Anchor::Start).unwrap();
+        let way = fuzzy::find_exact("way", "api-gateway", Anchor::End).unwrap();
+        assert_eq!(m.score, ap.score + way.score);
+        // Overlapping terms highlight each character once.
+        let m = Query::parse("api 'pi").find("api").unwrap();
+        assert_eq!(m.positions, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn search_ranks_extended_queries() {
+        let search = Search::new("api !gate", SearchMode::Fuzzy, SearchScope::Name).unwrap();
+        assert_eq!(
+            search.rank(
+                ["a-big-pile", "api-gateway", "rapid-api", "api"]
+                    .iter()
+                    .copied(),
+                str::cmp
+            ),
+            vec!["api", "rapid-api", "a-big-pile"]
+        );
+    }
+
+    #[test]
+    fn regex_mode_keeps_host_words_in_the_expression() {
+        let search = Search::new("host:\\d+", SearchMode::Regex, SearchScope::Name).unwrap();
+        assert!(search.hosts().is_empty());
+        assert_eq!(search.terms(), vec!["regex \"host:\\d+\""]);
+        assert!(search.is_regex());
+        assert!(search.find("host:42").is_some());
+    }
+
+    #[test]
+    fn search_terms_describe_the_parsed_query() {
+        let search = Search::new(
+            "host:gitlab api !tmp ^go host:x rs$",
+            SearchMode::Fuzzy,
+            SearchScope::Name,
+        )
+        .unwrap();
+        assert_eq!(search.hosts(), ["gitlab", "x"]);
+        assert_eq!(
+            search.terms(),
+            vec![
+                "fuzzy \"api\"",
+                "not exact \"tmp\"",
+                "prefix \"go\"",
+                "suffix \"rs\"",
+                "host \"gitlab\"",
+                "host \"x\"",
+            ]
+        );
+        let regex = Search::new("host:gh /^a.c$", SearchMode::Fuzzy, SearchScope::Name).unwrap();
+        assert_eq!(regex.terms(), vec!["regex \"^a.c$\"", "host \"gh\""]);
+        assert!(Search::new("", SearchMode::Fuzzy, SearchScope::Name)
+            .unwrap()
+            .terms()
+            .is_empty());
+        assert_eq!(
+            Search::new("^x$", SearchMode::Fuzzy, SearchScope::Name)
+                .unwrap()
+                .terms(),
+            vec!["equal \"x\""]
+        );
+    }
+
+    #[test]
+    fn search_scope_cycles() {
+        let scope = SearchScope::default();
+        assert_eq!(scope, SearchScope::Name);
+        assert_eq!(scope.next(), SearchScope::RelativePath);
+        assert_eq!(scope.next().next(), SearchScope::FullPath);
+        assert_eq!(scope.next().next().next(), SearchScope::Name);
+    }
+
+    #[test]
+    fn search_scope_text_is_relative_to_the_root() {
+        let roots = parse_roots(&BTreeMap::from([(
+            String::from("roots"),
+            String::from("/home/me/clients/:/home/me/clients/acme/vendor"),
+        )]));
+        let path = "/home/me/clients/acme/api";
+        assert_eq!(SearchScope::Name.text(path, &roots), None);
+        assert_eq!(
+            SearchScope::RelativePath.text(path, &roots).as_deref(),
+            Some("acme/api")
+        );
+        assert_eq!(
+            SearchScope::FullPath.text(path, &roots).as_deref(),
+            Some(path)
+        );
+        // The deepest root counts.
+        assert_eq!(
+            SearchScope::RelativePath
+                .text("/home/me/clients/acme/vendor/lib", &roots)
+                .as_deref(),
+            Some("lib")
+        );
+        // A root that is a project, and a project outside every root.
+        assert_eq!(
+            SearchScope::RelativePath
+                .text("/home/me/clients", &roots)
+                .as_deref(),
+            Some("clients")
+        );
+        assert_eq!(
+            SearchScope::RelativePath
+                .text("/etc/dotfiles", &roots)
+                .as_deref(),
+            Some("/etc/dotfiles")
+        );
+    }
+
+    #[test]
+    fn matched_segment_spans_the_matched_components() {
+        assert_eq!(
+            matched_segment("clients/acme/api", &[8, 9, 10, 11]),
+            (String::from("acme"), vec![0, 1, 2, 3])
+        );
+        assert_eq!(
+            matched_segment("clients/acme/api", &[9, 13]),
+            (String::from("acme/api"), vec![1, 5])
+        );
+        assert_eq!(
+            matched_segment("/home/me/src/acme", &[0, 1]),
+            (String::from("/home"), vec![0, 1])
+        );
+        assert_eq!(
+            matched_segment("/srv/café/x", &[8]),
+            (String::from("café"), vec![3])
+        );
+        assert_eq!(matched_segment("a/b", &[]), (String::from("a/b"), vec![]));
+    }
+
+    #[test]
+    fn path_matches_rank_below_name_matches() {
+        let paths = BTreeMap::from([
+            ("api", "/src/clients/acme/api"),
+            ("acme-web", "/src/acme-web"),
+            ("tools", "/src/clients/acme/tools"),
+            ("notes", "/src/notes"),
+        ]);
+        let path_text = |name: &str| paths.get(name).map(ToString::to_string);
+        let search = Search::new("acme", SearchMode::Fuzzy, SearchScope::Name).unwrap();
+        assert_eq!(
+            search.rank_with_paths(paths.keys().copied(), path_text, str::cmp),
+            vec!["acme-web", "api", "tools"]
+        );
+        assert_eq!(
+            search.rank_with_paths(paths.keys().copied(), |_| None, str::cmp),
+            vec!["acme-web"]
+        );
+        assert!(matches!(
+            search.find_project("acme-web", Some("/src/acme-web")),
+            Some(Found::Name(_))
+        ));
+        match search.find_project("api", path_text("api").as_deref()) {
+            Some(Found::Path(m)) => assert_eq!(m.positions, vec![13, 14, 15, 16]),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            search.find_project("notes", path_text("notes").as_deref()),
+            None
+        );
     }
 }
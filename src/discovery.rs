@@ -0,0 +1,887 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::core::{join_output, split_output, Root};
+use crate::exclude::Exclude;
+use crate::markers::Markers;
+
+/// Where the plugin sees the host filesystem. `change_host_folder` re-points
+/// this mount at a different host directory.
+pub const HOST_MOUNT: &str = "/host";
+
+/// How projects are discovered under the configured roots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Probe the command backends in [`PROBE_ORDER`] and use the first one
+    /// that is installed.
+    #[default]
+    Auto,
+    /// Shell out to the external `fd` binary (needs `RunCommands`).
+    Fd,
+    /// fd under the name Debian and Ubuntu install it as.
+    Fdfind,
+    /// POSIX `find`, present even on minimal systems.
+    Find,
+    /// Walk the roots in-plugin through the `/host` mount (needs `FullHdAccess`).
+    Native,
+}
+
+/// The command backends `Auto` tries, fastest first.
+pub const PROBE_ORDER: [Backend; 3] = [Backend::Fd, Backend::Fdfind, Backend::Find];
+
+impl Backend {
+    /// Read the `discovery` setting. Unknown values mean auto-detection.
+    #[must_use]
+    pub fn from_config(config: &BTreeMap<String, String>) -> Backend {
+        config
+            .get("discovery")
+            .and_then(|name| Backend::from_name(name))
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name.trim() {
+            "auto" => Some(Backend::Auto),
+            "fd" => Some(Backend::Fd),
+            "fdfind" => Some(Backend::Fdfind),
+            "find" => Some(Backend::Find),
+            "native" => Some(Backend::Native),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Auto => "auto",
+            Backend::Fd => "fd",
+            Backend::Fdfind => "fdfind",
+            Backend::Find => "find",
+            Backend::Native => "native",
+        }
+    }
+
+    /// The executable a command backend runs; None for the others.
+    #[must_use]
+    pub fn program(self) -> Option<&'static str> {
+        match self {
+            Backend::Fd => Some("fd"),
+            Backend::Fdfind => Some("fdfind"),
+            Backend::Find => Some("find"),
+            Backend::Auto | Backend::Native => None,
+        }
+    }
+}
+
+/// Whether a command result means the program itself is unavailable, as
+/// opposed to having run and failed. Zellij reports a spawn failure without
+/// an exit code; shells use 127 for "command not found".
+#[must_use]
+pub fn is_missing_program(exit_code: Option<i32>) -> bool {
+    matches!(exit_code, None | Some(127))
+}
+
+/// Per-root discovery progress, in configured order.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Progress {
+    roots: Vec<(String, bool)>,
+}
+
+impl Progress {
+    #[must_use]
+    pub fn start(roots: &[String]) -> Progress {
+        Progress {
+            roots: roots.iter().map(|r| (r.clone(), false)).collect(),
+        }
+    }
+
+    /// Mark `root` as searched. Unknown roots are ignored.
+    pub fn finish(&mut self, root: &str) {
+        for (r, done) in &mut self.roots {
+            if r == root {
+                *done = true;
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.roots.iter().all(|(_, done)| *done)
+    }
+
+    /// One header line while roots are outstanding, e.g.
+    /// `Searching roots 1/3, waiting on ~/work, ~/big`. None once all are in.
+    #[must_use]
+    pub fn summary(&self) -> Option<String> {
+        if self.is_done() {
+            return None;
+        }
+        let finished = self.roots.iter().filter(|(_, done)| *done).count();
+        let waiting: Vec<&str> = self
+            .roots
+            .iter()
+            .filter(|(_, done)| !*done)
+            .map(|(r, _)| r.as_str())
+            .collect();
+        Some(format!(
+            "Searching roots {finished}/{}, waiting on {}",
+            self.roots.len(),
+            waiting.join(", ")
+        ))
+    }
+}
+
+/// How many trailing stderr lines an error report shows.
+pub const STDERR_TAIL_LINES: usize = 3;
+
+/// Why discovery (or part of it) produced no projects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryError {
+    /// The configured backend's program is not installed.
+    MissingProgram { command: String },
+    /// Auto-detection found none of the command backends.
+    NoBackend,
+    /// The command ran and exited non-zero.
+    Failed {
+        command: String,
+        exit_code: i32,
+        stderr: String,
+    },
+    /// The native backend could not open a root.
+    UnreadableRoot { root: String, reason: String },
+    /// The command printed paths that are not valid UTF-8; they were skipped.
+    InvalidOutput { command: String },
+    /// A path in `setting` could not be expanded; the entry was skipped.
+    UnexpandedPath { setting: String, reason: String },
+    /// One root lies inside another, so its projects are found twice. A
+    /// warning: duplicates are merged by real path.
+    OverlappingRoots { outer: String, inner: String },
+    /// `zoxide` is set but zoxide is not installed.
+    MissingZoxide { command: String },
+    /// An `exclude` glob could not be parsed; it was skipped.
+    InvalidGlob { glob: String },
+    /// `editors` is set but their recent folders could not be read.
+    UnreadableEditors { reason: String },
+    /// `projects_file` is set but the file could not be read.
+    UnreadableProjectsFile { path: String, reason: String },
+}
+
+impl DiscoveryError {
+    /// The command line that failed, when there was one.
+    #[must_use]
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            DiscoveryError::MissingProgram { command }
+            | DiscoveryError::Failed { command, .. }
+            | DiscoveryError::InvalidOutput { command }
+            | DiscoveryError::MissingZoxide { command } => Some(command),
+            DiscoveryError::NoBackend
+            | DiscoveryError::UnreadableRoot { .. }
+            | DiscoveryError::UnexpandedPath { .. }
+            | DiscoveryError::OverlappingRoots { .. }
+            | DiscoveryError::InvalidGlob { .. }
+            | DiscoveryError::UnreadableEditors { .. }
+            | DiscoveryError::UnreadableProjectsFile { .. } => None,
+        }
+    }
+
+    /// The last `lines` non-blank lines of the command's stderr.
+    #[must_use]
+    pub fn stderr_tail(&self, lines: usize) -> Vec<&str> {
+        let DiscoveryError::Failed { stderr, .. } = self else {
+            return Vec::new();
+        };
+        let all: Vec<&str> = stderr
+            .lines()
+            .map(str::trim_end)
+            .filter(|l| !l.is_empty())
+            .collect();
+        all[all.len().saturating_sub(lines)..].to_vec()
+    }
+
+    /// What the user can do about it.
+    #[must_use]
+    pub fn hint(&self) -> &'static str {
+        match self {
+            DiscoveryError::MissingProgram { .. } => {
+                "install it, or remove the `discovery` setting to auto-detect a backend"
+            }
+            DiscoveryError::NoBackend => "install fd, or set `discovery \"native\"`",
+            DiscoveryError::Failed { .. } => "check that every path in `roots` exists",
+            DiscoveryError::UnreadableRoot { .. } => {
+                "check that the root exists and is an absolute path"
+            }
+            DiscoveryError::InvalidOutput { .. } => "rename or exclude the affected directories",
+            DiscoveryError::UnexpandedPath { .. } => {
+                "set it where zellij is started, or use an absolute path"
+            }
+            DiscoveryError::OverlappingRoots { .. } => {
+                "remove one of them, or use `exclude` to keep the outer root out of the inner one"
+            }
+            DiscoveryError::MissingZoxide { .. } => {
+                "install zoxide, or remove the `zoxide` setting"
+            }
+            DiscoveryError::InvalidGlob { .. } => "check the glob's `[...]` classes",
+            DiscoveryError::UnreadableEditors { .. } => {
+                "check that `HOME` is set where zellij is started, or remove the `editors` setting"
+            }
+            DiscoveryError::UnreadableProjectsFile { .. } => {
+                "check that the file exists, or remove the `projects_file` setting"
+            }
+        }
+    }
+
+    /// The plain-text lines the UI shows for this error: summary, command,
+    /// stderr tail and hint.
+    #[must_use]
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![self.to_string()];
+        if let Some(command) = self.command() {
+            lines.push(format!("  $ {command}"));
+        }
+        for line in self.stderr_tail(STDERR_TAIL_LINES) {
+            lines.push(format!("  | {line}"));
+        }
+        lines.push(format!("  hint: {}", self.hint()));
+        lines
+    }
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscoveryError::MissingProgram { command } => {
+                let program = command.split(' ').next().unwrap_or_default();
+                write!(f, "discovery: `{program}` is not installed")
+            }
+            DiscoveryError::NoBackend => {
+                write!(f, "discovery: none of fd, fdfind or find is available")
+            }
+            DiscoveryError::Failed { exit_code, .. } => {
+                write!(f, "discovery: command exited with status {exit_code}")
+            }
+            DiscoveryError::UnreadableRoot { root, reason } => {
+                write!(f, "discovery: cannot read {root}: {reason}")
+            }
+            DiscoveryError::InvalidOutput { .. } => {
+                write!(f, "discovery: skipped paths that are not valid UTF-8")
+            }
+            DiscoveryError::UnexpandedPath { setting, reason } => {
+                write!(f, "config: `{setting}`: {reason}")
+            }
+            DiscoveryError::OverlappingRoots { outer, inner } if outer == inner => {
+                write!(f, "config: root {outer} is listed twice")
+            }
+            DiscoveryError::OverlappingRoots { outer, inner } => {
+                write!(f, "config: root {inner} is inside root {outer}")
+            }
+            DiscoveryError::MissingZoxide { .. } => {
+                write!(f, "config: `zoxide` is set but zoxide is not installed")
+            }
+            DiscoveryError::InvalidGlob { glob } => {
+                write!(f, "config: `exclude`: cannot parse `{glob}`")
+            }
+            DiscoveryError::UnreadableEditors { reason } => {
+                write!(f, "config: `editors`: cannot read recent folders: {reason}")
+            }
+            DiscoveryError::UnreadableProjectsFile { path, reason } => {
+                write!(f, "config: `projects_file`: cannot read {path}: {reason}")
+            }
+        }
+    }
+}
+
+/// Decode a command's stdout. Entries that are not valid UTF-8 are dropped
+/// rather than mangled into paths that do not exist; the flag reports
+/// whether any were.
+#[must_use]
+pub fn decode_output(stdout: &[u8]) -> (String, bool) {
+    if let Ok(text) = std::str::from_utf8(stdout) {
+        return (text.to_string(), false);
+    }
+    let text = String::from_utf8_lossy(stdout);
+    let entries = split_output(&text)
+        .into_iter()
+        .filter(|entry| !entry.contains(char::REPLACEMENT_CHARACTER));
+    (join_output(entries), true)
+}
+
+/// Read the remembered auto-detected backend. Missing or unrecognised
+/// contents mean "probe again".
+#[must_use]
+pub fn read_backend(path: &Path) -> Option<Backend> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    Backend::from_name(&contents).filter(|b| b.program().is_some())
+}
+
+/// Remember the backend auto-detection settled on. Errors are logged and
+/// swallowed: the worst case is probing again next time.
+pub fn write_backend(path: &Path, backend: Backend) {
+    if let Err(e) = std::fs::write(path, backend.name()) {
+        eprintln!("discovery: failed to persist {}: {e}", path.display());
+    }
+}
+
+/// Forget the remembered backend, e.g. after it stopped working.
+pub fn forget_backend(path: &Path) {
+    std::fs::remove_file(path).ok();
+}
+
+/// Find entries named by `markers` below `mount`, which the host has mapped
+/// to `root`, skipping directories `exclude` matches. The root's options
+/// decide the depth and whether symlinks and hidden directories are followed.
+///
+/// Returns entries in the shape fd prints (`<root>/<repo>/.git/` for a marker
+/// directory, `<root>/<worktree>/.git` for a marker file) so both backends
+/// feed the same parser. Follows fd's depth semantics: entries
+/// directly inside the root are at depth 1, and markers shallower than the
+/// root's `min_depth` are skipped. Nothing below a marker is visited.
+/// Unreadable directories are skipped.
+#[must_use]
+pub fn walk_repositories(
+    mount: &Path,
+    root: &Root,
+    markers: &Markers,
+    exclude: &Exclude,
+) -> Vec<String> {
+    let walk = Walk {
+        root: root.path.trim_end_matches('/'),
+        min_depth: root.min_depth().unwrap_or(1),
+        max_depth: root.max_depth(),
+        follow: root.follow,
+        hidden: root.hidden,
+        markers,
+        exclude,
+    };
+    let mut found = Vec::new();
+    walk.visit(mount, "", 1, &mut found);
+    found.sort();
+    found
+}
+
+struct Walk<'a> {
+    root: &'a str,
+    min_depth: usize,
+    max_depth: usize,
+    follow: bool,
+    hidden: bool,
+    markers: &'a Markers,
+    exclude: &'a Exclude,
+}
+
+impl Walk<'_> {
+    /// Visit `dir`, which is `relative` below the root.
+    fn visit(&self, dir: &Path, relative: &str, depth: usize, found: &mut Vec<String>) {
+        if depth > self.max_depth {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let Ok(mut file_type) = entry.file_type() else {
+                continue;
+            };
+            if self.follow && file_type.is_symlink() {
+                let Ok(target) = std::fs::metadata(entry.path()) else {
+                    continue;
+                };
+                file_type = target.file_type();
+            }
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let path = format!("{relative}/{name}");
+            let marker = self.markers.contains(&name);
+            if marker && depth < self.min_depth {
+                // Too shallow to be a project; nothing inside counts either.
+                continue;
+            }
+            if file_type.is_file() {
+                if marker {
+                    found.push(format!("{}{path}", self.root));
+                }
+            } else if file_type.is_dir()
+                && !self.exclude.excludes(&path)
+                && (self.hidden || marker || !name.starts_with('.'))
+            {
+                if marker {
+                    found.push(format!("{}{path}/", self.root));
+                } else {
+                    self.visit(&entry.path(), &path, depth + 1, found);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::test_util::scratch;
+
+    #[test]
+    fn backend_defaults_to_auto() {
+        assert_eq!(Backend::from_config(&BTreeMap::new()), Backend::Auto);
+        let config = BTreeMap::from([(String::from("discovery"), String::from("bogus"))]);
+        assert_eq!(Backend::from_config(&config), Backend::Auto);
+    }
+
+    #[test]
+    fn backend_names_round_trip() {
+        for backend in [
+            Backend::Auto,
+            Backend::Fd,
+            Backend::Fdfind,
+            Backend::Find,
+            Backend::Native,
+        ] {
+            assert_eq!(Backend::from_name(backend.name()), Some(backend));
+        }
+    }
+
+    #[test]
+    fn probing_tries_fd_then_fdfind_then_find() {
+        assert_eq!(PROBE_ORDER, [Backend::Fd, Backend::Fdfind, Backend::Find]);
+    }
+
+    #[test]
+    fn missing_program_is_no_exit_code_or_127() {
+        assert!(is_missing_program(None));
+        assert!(is_missing_program(Some(127)));
+        assert!(!is_missing_program(Some(0)));
+        assert!(!is_missing_program(Some(1)));
+    }
+
+    #[test]
+    fn progress_tracks_roots_in_configured_order() {
+        let roots = vec![
+            String::from("~/a"),
+            String::from("~/b"),
+            String::from("~/c"),
+        ];
+        let mut progress = Progress::start(&roots);
+        assert_eq!(
+            progress.summary(),
+            Some(String::from(
+                "Searching roots 0/3, waiting on ~/a, ~/b, ~/c"
+            ))
+        );
+        progress.finish("~/b");
+        progress.finish("~/unknown");
+        assert_eq!(
+            progress.summary(),
+            Some(String::from("Searching roots 1/3, waiting on ~/a, ~/c"))
+        );
+        progress.finish("~/a");
+        progress.finish("~/c");
+        assert!(progress.is_done());
+        assert_eq!(progress.summary(), None);
+    }
+
+    #[test]
+    fn failed_report_shows_command_stderr_tail_and_hint() {
+        let error = DiscoveryError::Failed {
+            command: String::from("fd -Htd --max-depth=2 ^\\.git$ ~/nope"),
+            exit_code: 1,
+            stderr: String::from("one\ntwo\n\nthree\nfour\n"),
+        };
+        assert_eq!(
+            error.report(),
+            vec![
+                "discovery: command exited with status 1",
+                "  $ fd -Htd --max-depth=2 ^\\.git$ ~/nope",
+                "  | two",
+                "  | three",
+                "  | four",
+                "  hint: check that every path in `roots` exists",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_program_names_the_program() {
+        let error = DiscoveryError::MissingProgram {
+            command: String::from("fdfind -Htd ~"),
+        };
+        assert_eq!(error.to_string(), "discovery: `fdfind` is not installed");
+        assert_eq!(error.command(), Some("fdfind -Htd ~"));
+    }
+
+    #[test]
+    fn errors_without_a_command_skip_that_line() {
+        let error = DiscoveryError::UnreadableRoot {
+            root: String::from("/missing"),
+            reason: String::from("not found"),
+        };
+        assert_eq!(
+            error.report(),
+            vec![
+                "discovery: cannot read /missing: not found",
+                "  hint: check that the root exists and is an absolute path",
+            ]
+        );
+        assert_eq!(DiscoveryError::NoBackend.report().len(), 2);
+    }
+
+    #[test]
+    fn overlapping_roots_are_reported() {
+        let error = DiscoveryError::OverlappingRoots {
+            outer: String::from("/src"),
+            inner: String::from("/src/work"),
+        };
+        assert_eq!(
+            error.to_string(),
+            "config: root /src/work is inside root /src"
+        );
+        let error = DiscoveryError::OverlappingRoots {
+            outer: String::from("/src"),
+            inner: String::from("/src"),
+        };
+        assert_eq!(error.to_string(), "config: root /src is listed twice");
+    }
+
+    #[test]
+    fn missing_zoxide_shows_the_query() {
+        let error = DiscoveryError::MissingZoxide {
+            command: String::from("zoxide query -l -s"),
+        };
+        assert_eq!(
+            error.report(),
+            vec![
+                "config: `zoxide` is set but zoxide is not installed",
+                "  $ zoxide query -l -s",
+                "  hint: install zoxide, or remove the `zoxide` setting",
+            ]
+        );
+    }
+
+    #[test]
+    fn unexpanded_paths_name_the_setting() {
+        let error = DiscoveryError::UnexpandedPath {
+            setting: String::from("roots"),
+            reason: String::from("cannot expand `$WORK/src`: `WORK` is not set"),
+        };
+        assert_eq!(
+            error.report(),
+            vec![
+                "config: `roots`: cannot expand `$WORK/src`: `WORK` is not set",
+                "  hint: set it where zellij is started, or use an absolute path",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_globs_are_named() {
+        let error = DiscoveryError::InvalidGlob {
+            glob: String::from("[z-a]"),
+        };
+        assert_eq!(
+            error.report(),
+            vec![
+                "config: `exclude`: cannot parse `[z-a]`",
+                "  hint: check the glob's `[...]` classes",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreadable_editors_say_why() {
+        let error = DiscoveryError::UnreadableEditors {
+            reason: String::from("`HOME` is not set"),
+        };
+        assert_eq!(
+            error.report(),
+            vec![
+                "config: `editors`: cannot read recent folders: `HOME` is not set",
+                "  hint: check that `HOME` is set where zellij is started, or remove the `editors` setting",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreadable_projects_file_says_why() {
+        let error = DiscoveryError::UnreadableProjectsFile {
+            path: String::from("/home/me/projects.txt"),
+            reason: String::from("No such file or directory"),
+        };
+        assert_eq!(error.command(), None);
+        assert_eq!(
+            error.report(),
+            vec![
+                "config: `projects_file`: cannot read /home/me/projects.txt: No such file or directory",
+                "  hint: check that the file exists, or remove the `projects_file` setting",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_keeps_valid_lines_of_invalid_output() {
+        assert_eq!(
+            decode_output(b"/src/a/.git/\n"),
+            (String::from("/src/a/.git/\n"), false)
+        );
+        assert_eq!(
+            decode_output(b"/src/a/.git/\n/src/\xff/.git/\n/src/b/.git/\n"),
+            (String::from("/src/a/.git/\0/src/b/.git/\0"), true)
+        );
+        assert_eq!(
+            decode_output(b"/src/a/.git/\0/src/\xff\n/.git/\0"),
+            (String::from("/src/a/.git/\0"), true)
+        );
+    }
+
+    #[test]
+    fn remembered_backend_round_trips() {
+        let dir = PathBuf::from("target/zps-backend-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("discovery-backend.v1");
+        write_backend(&path, Backend::Fdfind);
+        assert_eq!(read_backend(&path), Some(Backend::Fdfind));
+        forget_backend(&path);
+        assert_eq!(read_backend(&path), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn remembered_backend_ignores_non_command_backends() {
+        let dir = PathBuf::from("target/zps-backend-junk-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("discovery-backend.v1");
+        std::fs::write(&path, "auto").unwrap();
+        assert_eq!(read_backend(&path), None);
+        std::fs::write(&path, "garbage").unwrap();
+        assert_eq!(read_backend(&path), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn backend_native_is_selectable() {
+        let config = BTreeMap::from([(String::from("discovery"), String::from("native"))]);
+        assert_eq!(Backend::from_config(&config), Backend::Native);
+    }
+
+    #[test]
+    fn walk_finds_repositories_within_depth() {
+        let dir = scratch(
+            "zps-walk-depth-test",
+            &[
+                "alpha/.git",
+                "beta/.git",
+                "group/gamma/.git",
+                ".hidden/.git",
+            ],
+        );
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::new("/src/"),
+                &Markers::default(),
+                &Exclude::default()
+            ),
+            vec!["/src/.hidden/.git/", "/src/alpha/.git/", "/src/beta/.git/",]
+        );
+        let deep = Root::parse("/src[depth=2]");
+        assert_eq!(
+            walk_repositories(&dir, &deep, &Markers::default(), &Exclude::default()),
+            vec![
+                "/src/.hidden/.git/",
+                "/src/alpha/.git/",
+                "/src/beta/.git/",
+                "/src/group/gamma/.git/",
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_finds_the_root_itself_and_skips_git_internals() {
+        let dir = scratch("zps-walk-root-test", &[".git/modules/sub/.git"]);
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::new("/repo"),
+                &Markers::default(),
+                &Exclude::default()
+            ),
+            vec!["/repo/.git/"]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_reports_git_files_without_trailing_slash() {
+        let dir = scratch("zps-walk-worktree-test", &["api/.git", "api-feature"]);
+        std::fs::write(
+            dir.join("api-feature/.git"),
+            "gitdir: /src/api/.git/worktrees/api-feature\n",
+        )
+        .unwrap();
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::new("/src"),
+                &Markers::default(),
+                &Exclude::default()
+            ),
+            vec!["/src/api-feature/.git", "/src/api/.git/"]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_finds_configured_vcs_markers() {
+        let dir = scratch(
+            "zps-walk-vcs-test",
+            &["jj/.jj", "jj/.git", "hg/.hg", "pijul/.pijul", "fossil"],
+        );
+        std::fs::write(dir.join("fossil/.fslckout"), "").unwrap();
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::new("/src"),
+                &Markers::default(),
+                &Exclude::default()
+            ),
+            vec![
+                "/src/fossil/.fslckout",
+                "/src/hg/.hg/",
+                "/src/jj/.git/",
+                "/src/jj/.jj/",
+                "/src/pijul/.pijul/",
+            ]
+        );
+        let git_only = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git"),
+        )]));
+        assert_eq!(
+            walk_repositories(&dir, &Root::new("/src"), &git_only, &Exclude::default()),
+            vec!["/src/jj/.git/"]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_finds_marker_files() {
+        let dir = scratch("zps-walk-marker-test", &["app/.git", "scratch", "ws/core"]);
+        std::fs::write(dir.join("scratch/flake.nix"), "").unwrap();
+        std::fs::write(dir.join("ws/Cargo.toml"), "").unwrap();
+        std::fs::write(dir.join("ws/core/Cargo.toml"), "").unwrap();
+        let markers = Markers::from_config(&BTreeMap::from([(
+            String::from("markers"),
+            String::from(".git:Cargo.toml:flake.nix"),
+        )]));
+        assert_eq!(
+            walk_repositories(&dir, &Root::new("/src"), &markers, &Exclude::default()),
+            vec![
+                "/src/app/.git/",
+                "/src/scratch/flake.nix",
+                "/src/ws/Cargo.toml"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_skips_excluded_directories() {
+        let dir = scratch(
+            "zps-walk-exclude-test",
+            &[
+                "api/.git",
+                "node_modules/pkg/.git",
+                "archive/.git",
+                "web/archive/.git",
+            ],
+        );
+        let exclude = Exclude::from_config(&BTreeMap::from([(
+            String::from("exclude"),
+            String::from("node_modules:/archive"),
+        )]));
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::parse("/src[depth=2]"),
+                &Markers::default(),
+                &exclude
+            ),
+            vec!["/src/api/.git/", "/src/web/archive/.git/"]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_can_skip_hidden_directories() {
+        let dir = scratch("zps-walk-hidden-test", &["api/.git", ".config/nvim/.git"]);
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::parse("/src[hidden=false]"),
+                &Markers::default(),
+                &Exclude::default()
+            ),
+            vec!["/src/api/.git/"]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_follows_symlinks_when_asked() {
+        let dir = scratch("zps-walk-follow-test", &["real/api/.git", "src"]);
+        std::os::unix::fs::symlink("../real/api", dir.join("src/linked")).unwrap();
+        let root = |options: &str| Root::parse(&format!("/src[depth=2{options}]"));
+        let walk = |root: &Root| {
+            walk_repositories(
+                &dir.join("src"),
+                root,
+                &Markers::default(),
+                &Exclude::default(),
+            )
+        };
+        assert!(walk(&root("")).is_empty());
+        assert_eq!(walk(&root(",follow")), vec!["/src/linked/.git/"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_of_a_ghq_root_finds_only_host_org_repo() {
+        let dir = scratch(
+            "zps-walk-ghq-test",
+            &[
+                ".git",
+                "github.com/acme/app/.git",
+                "github.com/acme/.git",
+                "github.com/acme/app/vendor/lib/.git",
+                "gitlab.com/team/site/.jj",
+            ],
+        );
+        assert_eq!(
+            walk_repositories(
+                &dir,
+                &Root::parse("/ghq[layout=ghq]"),
+                &Markers::default(),
+                &Exclude::default(),
+            ),
+            vec![
+                "/ghq/github.com/acme/app/.git/",
+                "/ghq/gitlab.com/team/site/.jj/"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walk_of_missing_mount_is_empty() {
+        assert!(walk_repositories(
+            Path::new("/nonexistent/mount"),
+            &Root::new("/src"),
+            &Markers::default(),
+            &Exclude::default()
+        )
+        .is_empty());
+    }
+}
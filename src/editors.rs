@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::core::normalise_path;
+
+/// Where VS Code and its builds keep their state, relative to the home
+/// directory, on Linux and macOS.
+const VSCODE_DIRS: &[&str] = &[
+    ".config/Code",
+    ".config/Code - Insiders",
+    ".config/VSCodium",
+    "Library/Application Support/Code",
+    "Library/Application Support/Code - Insiders",
+    "Library/Application Support/VSCodium",
+];
+
+/// The files below a VS Code state directory that list recent folders:
+/// older releases keep them in `storage.json`, newer ones in the
+/// `state.vscdb` `SQLite` database.
+const VSCODE_FILES: &[&str] = &[
+    "User/globalStorage/storage.json",
+    "User/globalStorage/state.vscdb",
+];
+
+/// Where `JetBrains` IDEs keep one configuration directory per product and
+/// version (`IntelliJIdea2024.1`, `PyCharm2023.3`, ...).
+const JETBRAINS_DIRS: &[&str] = &[".config/JetBrains", "Library/Application Support/JetBrains"];
+
+/// The files below a `JetBrains` configuration directory that list recent
+/// projects; Rider calls its projects solutions.
+const JETBRAINS_FILES: &[&str] = &["options/recentProjects.xml", "options/recentSolutions.xml"];
+
+/// A local folder in VS Code's recently opened list.
+static FOLDER_URI: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""folderUri"\s*:\s*"file://([^"]*)""#).unwrap());
+
+/// A project path in a `JetBrains` recent projects file.
+static PROJECT_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<(?:entry key|option value)="([^"]*)""#).unwrap());
+
+/// An editor whose recently opened folders can be listed as projects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Editor {
+    VsCode,
+    JetBrains,
+}
+
+impl Editor {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Editor::VsCode => "vscode",
+            Editor::JetBrains => "jetbrains",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Editor> {
+        [Editor::VsCode, Editor::JetBrains]
+            .iter()
+            .copied()
+            .find(|e| e.name() == name)
+    }
+
+    /// Read the `editors` setting: editor names separated by `:`, e.g.
+    /// `vscode:jetbrains`. Unknown names are skipped; unset lists none.
+    #[must_use]
+    pub fn from_config(config: &BTreeMap<String, String>) -> Vec<Editor> {
+        let editors: BTreeSet<Editor> = config
+            .get("editors")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(':')
+            .filter_map(|name| Editor::from_name(name.trim()))
+            .collect();
+        editors.into_iter().collect()
+    }
+}
+
+/// The local folders in VS Code's recently opened list, from `storage.json`
+/// or from the raw bytes of `state.vscdb` (decoded lossily), where the list
+/// is stored as JSON text. Remote folders and files are skipped.
+#[must_use]
+pub fn vscode_folders(contents: &str) -> Vec<String> {
+    FOLDER_URI
+        .captures_iter(contents)
+        .filter_map(|c| percent_decode(&c[1]))
+        .filter(|path| path.starts_with('/'))
+        .map(|path| normalise_path(&path))
+        .collect()
+}
+
+/// The projects in a `JetBrains` `recentProjects.xml`: the keys of its project
+/// map, or the `recentPaths` list of older releases, with `$USER_HOME$`
+/// replaced by `home`.
+#[must_use]
+pub fn jetbrains_projects(contents: &str, home: &str) -> Vec<String> {
+    PROJECT_PATH
+        .captures_iter(contents)
+        .map(|c| xml_unescape(&c[1]).replace("$USER_HOME$", home.trim_end_matches('/')))
+        .filter(|path| path.starts_with('/'))
+        .map(|path| normalise_path(&path))
+        .collect()
+}
+
+/// Read the recent folders of `editors` below `mount`, which the host has
+/// mapped to `/`, for the user whose home directory is `home`. Folders that
+/// no longer exist are skipped, and so are editor files that are missing or
+/// can't be read, since few users have every editor. Only a `/host` that
+/// can't be mapped is reported, by the caller, as
+/// `DiscoveryError::UnreadableEditors`.
+#[must_use]
+pub fn read_recent(mount: &Path, home: &str, editors: &[Editor]) -> BTreeSet<String> {
+    let host = |path: &str| mount.join(path.trim_start_matches('/'));
+    let read = |path: &Path| {
+        std::fs::read(path)
+            .ok()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    };
+    let home_dir = host(home);
+    let mut found = Vec::new();
+    for editor in editors {
+        match editor {
+            Editor::VsCode => {
+                for dir in VSCODE_DIRS {
+                    for file in VSCODE_FILES {
+                        if let Some(contents) = read(&home_dir.join(dir).join(file)) {
+                            found.extend(vscode_folders(&contents));
+                        }
+                    }
+                }
+            }
+            Editor::JetBrains => {
+                for dir in JETBRAINS_DIRS {
+                    let Ok(products) = std::fs::read_dir(home_dir.join(dir)) else {
+                        continue;
+                    };
+                    for product in products.flatten() {
+                        for file in JETBRAINS_FILES {
+                            if let Some(contents) = read(&product.path().join(file)) {
+                                found.extend(jetbrains_projects(&contents, home));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    found
+        .into_iter()
+        .filter(|path| path != "/" && host(path).is_dir())
+        .collect()
+}
+
+/// Decode `%XX` escapes in a URI path. None when they do not decode to
+/// UTF-8.
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::scratch;
+
+    #[test]
+    fn editors_are_opt_in() {
+        assert!(Editor::from_config(&BTreeMap::new()).is_empty());
+        let config = BTreeMap::from([(
+            String::from("editors"),
+            String::from("jetbrains: vim :vscode:jetbrains"),
+        )]);
+        assert_eq!(
+            Editor::from_config(&config),
+            vec![Editor::VsCode, Editor::JetBrains]
+        );
+    }
+
+    #[test]
+    fn vscode_folders_are_local_folder_uris() {
+        let json = r#"{"entries":[{"folderUri":"file:///home/me/src/app"},{"fileUri":"file:///home/me/notes.md"},{"folderUri":"vscode-remote://ssh-remote%2Bbox/srv/app"},{"folderUri": "file:///home/me/My%20Project/"},{"workspace":{"id":"x","configPath":"file:///home/me/w.code-workspace"}}]}"#;
+        assert_eq!(
+            vscode_folders(json),
+            vec!["/home/me/src/app", "/home/me/My Project"]
+        );
+    }
+
+    #[test]
+    fn vscode_folders_survive_binary_noise() {
+        let db = b"SQLite format 3\0\x01\xff\xfehistory.recentlyOpenedPathsList{\"entries\":[{\"folderUri\":\"file:///home/me/caf%C3%A9\"}]}\0\0";
+        assert_eq!(
+            vscode_folders(&String::from_utf8_lossy(db)),
+            vec!["/home/me/café"]
+        );
+        assert!(vscode_folders(r#"{"folderUri":"file:///bad%ff"}"#).is_empty());
+    }
+
+    #[test]
+    fn jetbrains_projects_expand_the_home_directory() {
+        let xml = r#"<application>
+  <component name="RecentProjectsManager">
+    <option name="additionalInfo">
+      <map>
+        <entry key="$USER_HOME$/IdeaProjects/api">
+          <value><RecentProjectMetaInfo frameTitle="api" /></value>
+        </entry>
+        <entry key="/opt/work/R&amp;D" />
+      </map>
+    </option>
+    <option name="lastProjectLocation" value="$USER_HOME$/IdeaProjects" />
+    <option name="recentPaths">
+      <list>
+        <option value="$USER_HOME$/old" />
+      </list>
+    </option>
+  </component>
+</application>"#;
+        assert_eq!(
+            jetbrains_projects(xml, "/home/me/"),
+            vec!["/home/me/IdeaProjects/api", "/opt/work/R&D", "/home/me/old"]
+        );
+    }
+
+    #[test]
+    fn read_recent_skips_folders_that_are_gone() {
+        let mount = scratch(
+            "zps-editors-test",
+            &[
+                "home/me/.config/Code/User/globalStorage",
+                "home/me/.config/JetBrains/IntelliJIdea2024.1/options",
+                "home/me/src/app",
+                "srv/api",
+            ],
+        );
+        let storage = mount.join("home/me/.config/Code/User/globalStorage");
+        let idea = mount.join("home/me/.config/JetBrains/IntelliJIdea2024.1/options");
+        std::fs::write(
+            storage.join("storage.json"),
+            r#"{"folderUri":"file:///home/me/src/app","x":{"folderUri":"file:///home/me/gone"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            idea.join("recentProjects.xml"),
+            r#"<entry key="/srv/api"/><entry key="$USER_HOME$/src/app"/>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            read_recent(&mount, "/home/me", &[Editor::VsCode, Editor::JetBrains]),
+            BTreeSet::from([String::from("/home/me/src/app"), String::from("/srv/api")])
+        );
+        assert!(read_recent(&mount, "/home/me", &[]).is_empty());
+        std::fs::remove_dir_all(&mount).ok();
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::core::{join_output, split_output};
+use crate::stack::{escape_field, tmp_path, unescape_field};
+
+const HIDDEN_HEADER: &str = "hidden.v1";
+
+/// One `exclude` glob, with gitignore's anchoring rules: a glob without a
+/// `/` matches an entry of that name at any depth, one with a `/` matches a
+/// path relative to the root.
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: String,
+    anchored: bool,
+    regex: Regex,
+}
+
+/// The `exclude` setting: globs for directories discovery does not descend
+/// into, e.g. `node_modules`, `archive/` or `vendor/*`.
+#[derive(Debug, Clone, Default)]
+pub struct Exclude {
+    patterns: Vec<Pattern>,
+    invalid: Vec<String>,
+}
+
+impl Exclude {
+    /// Read the `exclude` setting: globs separated by `:`. `*` and `?` do
+    /// not match `/`, `**` does, and `[...]` is a character class. Globs that
+    /// cannot be parsed are skipped, and listed by `invalid`.
+    #[must_use]
+    pub fn from_config(config: &BTreeMap<String, String>) -> Exclude {
+        let mut exclude = Exclude::default();
+        let globs = config
+            .get("exclude")
+            .map(String::as_str)
+            .unwrap_or_default();
+        for glob in globs.split(':') {
+            let glob = glob.trim().trim_end_matches('/');
+            if glob.is_empty() {
+                continue;
+            }
+            match Regex::new(&glob_regex(glob.trim_start_matches('/'))) {
+                Ok(regex) => exclude.patterns.push(Pattern {
+                    glob: glob.to_string(),
+                    anchored: glob.contains('/'),
+                    regex,
+                }),
+                Err(_) => exclude.invalid.push(glob.to_string()),
+            }
+        }
+        exclude
+    }
+
+    /// The globs that could not be parsed, such as `[z-a]`.
+    #[must_use]
+    pub fn invalid(&self) -> &[String] {
+        &self.invalid
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// The globs as configured, for fd's `--exclude`.
+    #[must_use]
+    pub fn globs(&self) -> Vec<&str> {
+        self.patterns.iter().map(|p| p.glob.as_str()).collect()
+    }
+
+    /// The globs `find` can prune by name: those without a `/` or `**`.
+    #[must_use]
+    pub fn name_globs(&self) -> Vec<&str> {
+        self.patterns
+            .iter()
+            .filter(|p| !p.anchored && !p.glob.contains("**"))
+            .map(|p| p.glob.as_str())
+            .collect()
+    }
+
+    /// Whether `relative`, a path below a root, is excluded: some entry on
+    /// the way to it matches an unanchored glob by name, or an anchored glob
+    /// by its path from the root.
+    #[must_use]
+    pub fn excludes(&self, relative: &str) -> bool {
+        let parts: Vec<&str> = relative.split('/').filter(|c| !c.is_empty()).collect();
+        (0..parts.len()).any(|i| {
+            let prefix = parts[..=i].join("/");
+            self.patterns.iter().any(|p| {
+                if p.anchored {
+                    p.regex.is_match(&prefix)
+                } else {
+                    p.regex.is_match(parts[i])
+                }
+            })
+        })
+    }
+
+    /// Drop the entries of discovery output for `root` that lie below an
+    /// excluded directory. Backends that cannot exclude while searching rely
+    /// on this.
+    #[must_use]
+    pub fn filter_output(&self, root: &str, output: &str) -> String {
+        let root = root.trim_end_matches('/');
+        join_output(split_output(output).into_iter().filter(|entry| {
+            let relative = entry.strip_prefix(root).unwrap_or(entry);
+            !self.excludes(relative)
+        }))
+    }
+}
+
+/// Translate a glob into an anchored regex.
+fn glob_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    class.push(c);
+                }
+                if closed && !class.is_empty() {
+                    re.push_str(&class_regex(&class));
+                } else {
+                    re.push_str(&regex::escape(&format!("[{class}")));
+                    if closed {
+                        re.push_str(&regex::escape("]"));
+                    }
+                }
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    re
+}
+
+/// Translate the body of a glob's `[...]` class. Only a leading `!` or `^`
+/// and the `-` of a range keep a meaning; everything else is literal, so
+/// regex set operations such as `&&`, `--` or `~~` and nested classes
+/// cannot sneak in.
+fn class_regex(class: &str) -> String {
+    let (negated, body) = match class.strip_prefix(&['!', '^'][..]) {
+        Some(body) => (true, body),
+        None => (false, class),
+    };
+    let chars: Vec<char> = body.chars().collect();
+    let mut re = String::from(if negated { "[^" } else { "[" });
+    for (i, &c) in chars.iter().enumerate() {
+        let range = c == '-' && i > 0 && i + 1 < chars.len() && chars[i - 1] != '-';
+        if range {
+            re.push('-');
+        } else {
+            re.push_str(&regex::escape(&c.to_string()));
+        }
+    }
+    re.push(']');
+    re
+}
+
+/// Parse the list of hidden project paths. Total: a missing header yields an
+/// empty list.
+#[must_use]
+pub fn parse_hidden(contents: &str) -> BTreeSet<String> {
+    let mut lines = contents.lines();
+    if lines.next() != Some(HIDDEN_HEADER) {
+        return BTreeSet::new();
+    }
+    lines
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(unescape_field)
+        .collect()
+}
+
+#[must_use]
+pub fn serialize_hidden(hidden: &BTreeSet<String>) -> String {
+    let mut out = format!("{HIDDEN_HEADER}\n");
+    for path in hidden {
+        out.push_str(&escape_field(path));
+        out.push('\n');
+    }
+    out
+}
+
+/// Read the paths of projects hidden from the list; empty on any failure.
+#[must_use]
+pub fn read_hidden(path: &Path) -> BTreeSet<String> {
+    std::fs::read_to_string(path)
+        .map(|contents| parse_hidden(&contents))
+        .unwrap_or_default()
+}
+
+/// Persist the hidden projects atomically, as `stack::write_stack` does.
+/// Errors are logged and swallowed.
+pub fn write_hidden(path: &Path, hidden: &BTreeSet<String>) {
+    let tmp = tmp_path(path);
+    let result =
+        std::fs::write(&tmp, serialize_hidden(hidden)).and_then(|()| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        eprintln!("hidden-projects: failed to persist {}: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::scratch;
+
+    fn exclude(globs: &str) -> Exclude {
+        Exclude::from_config(&BTreeMap::from([(
+            String::from("exclude"),
+            String::from(globs),
+        )]))
+    }
+
+    #[test]
+    fn exclude_defaults_to_nothing() {
+        let none = Exclude::from_config(&BTreeMap::new());
+        assert!(none.is_empty());
+        assert!(!none.excludes("node_modules/x"));
+    }
+
+    #[test]
+    fn unanchored_globs_match_names_at_any_depth() {
+        let ex = exclude("node_modules:*.bak");
+        assert!(ex.excludes("node_modules/left-pad/.git/"));
+        assert!(ex.excludes("web/node_modules/.git/"));
+        assert!(ex.excludes("old.bak/.git/"));
+        assert!(!ex.excludes("node_modules2/.git/"));
+        assert!(!ex.excludes("web/.git/"));
+    }
+
+    #[test]
+    fn anchored_globs_match_from_the_root() {
+        let ex = exclude("/archive:vendor/*:/registry");
+        assert!(ex.excludes("archive/old/.git/"));
+        assert!(ex.excludes("vendor/lib/.git/"));
+        assert!(ex.excludes("registry/.git/"));
+        assert!(!ex.excludes("work/archive/.git/"));
+        assert!(!ex.excludes("lib/vendor/x/.git/"));
+        assert_eq!(ex.globs(), vec!["/archive", "vendor/*", "/registry"]);
+        assert!(ex.name_globs().is_empty());
+    }
+
+    #[test]
+    fn trailing_slash_does_not_anchor() {
+        let ex = exclude("archive/");
+        assert!(ex.excludes("work/archive/.git/"));
+        assert_eq!(ex.name_globs(), vec!["archive"]);
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        let ex = exclude("**/cache/**");
+        assert!(ex.excludes("a/b/cache/x/.git/"));
+        assert!(ex.excludes("cache/x/.git/"));
+        assert!(!ex.excludes("cached/.git/"));
+    }
+
+    #[test]
+    fn character_classes_and_question_marks() {
+        let ex = exclude("tmp[0-9]:[!a-z]x:v?");
+        assert!(ex.excludes("tmp3/.git/"));
+        assert!(!ex.excludes("tmpa/.git/"));
+        assert!(ex.excludes("9x/.git/"));
+        assert!(!ex.excludes("ax/.git/"));
+        assert!(ex.excludes("v1/.git/"));
+        assert!(!ex.excludes("v12/.git/"));
+    }
+
+    #[test]
+    fn class_bodies_are_literal_apart_from_ranges() {
+        let ex = exclude("x[&&y]:z[a~~b]:[-.]v:q[[]:[^0-9]w");
+        assert!(ex.invalid().is_empty());
+        assert!(ex.excludes("x&/.git/"));
+        assert!(ex.excludes("xy/.git/"));
+        assert!(!ex.excludes("xa/.git/"));
+        assert!(ex.excludes("z~/.git/"));
+        assert!(ex.excludes("-v/.git/"));
+        assert!(ex.excludes(".v/.git/"));
+        assert!(!ex.excludes("av/.git/"));
+        assert!(ex.excludes("q[/.git/"));
+        assert!(ex.excludes("aw/.git/"));
+        assert!(!ex.excludes("1w/.git/"));
+    }
+
+    #[test]
+    fn invalid_globs_are_listed_and_skipped() {
+        let ex = exclude("[z-a]:node_modules");
+        assert_eq!(ex.invalid(), ["[z-a]"]);
+        assert_eq!(ex.globs(), vec!["node_modules"]);
+    }
+
+    #[test]
+    fn unbalanced_brackets_are_literal() {
+        let ex = exclude("[oops");
+        assert!(ex.excludes("[oops/.git/"));
+        assert_eq!(ex.name_globs(), vec!["[oops"]);
+    }
+
+    #[test]
+    fn filter_output_drops_excluded_entries() {
+        let ex = exclude("node_modules:/archive");
+        assert_eq!(
+            ex.filter_output(
+                "/src/",
+                "/src/api/.git/\0/src/node_modules/x/.git/\0/src/archive/.git/\0/src/web/archive/.git/\0"
+            ),
+            "/src/api/.git/\0/src/web/archive/.git/\0"
+        );
+        assert_eq!(
+            ex.filter_output("/src", "/src/api/.git/\n/src/archive/.git/\n"),
+            "/src/api/.git/\0"
+        );
+    }
+
+    #[test]
+    fn hidden_round_trips() {
+        let hidden = BTreeSet::from([
+            String::from("/src/a"),
+            String::from("/src/b c"),
+            String::from("/src/two\nlines"),
+        ]);
+        let text = serialize_hidden(&hidden);
+        assert_eq!(text.lines().count(), 4);
+        assert_eq!(parse_hidden(&text), hidden);
+        assert!(parse_hidden("/src/a\n").is_empty());
+    }
+
+    #[test]
+    fn write_then_read_hidden() {
+        let dir = scratch("zps-hidden-test", &[]);
+        let path = dir.join("hidden-projects.v1");
+        let hidden = BTreeSet::from([String::from("/src/noise")]);
+        write_hidden(&path, &hidden);
+        assert_eq!(read_hidden(&path), hidden);
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(read_hidden(&path).is_empty());
+    }
+}
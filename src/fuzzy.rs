@@ -0,0 +1,410 @@
+use std::cmp::Ordering;
+
+/// Each matched character.
+const SCORE_MATCH: i64 = 16;
+/// Skipping candidate characters between two matched ones: the first
+/// skipped character, then each further one.
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+/// Matching the first character of the candidate.
+const BONUS_PREFIX: i64 = 10;
+/// Matching the first character of a word: after `-`, `_`, `/`, `.`, a space
+/// or any other non-alphanumeric character.
+const BONUS_BOUNDARY: i64 = 8;
+/// Matching an upper-case letter after a lower-case one (`camelCase`).
+const BONUS_CAMEL: i64 = 7;
+/// Matching the character after the previous match; a run keeps the bonus
+/// of the character it started on when that is higher.
+const BONUS_CONSECUTIVE: i64 = 4;
+/// The first pattern character's bonus counts this many times, so where a
+/// match starts matters most.
+const FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// How a candidate matched: its score and the positions, in characters,
+/// of the matched characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+/// Match `candidate` against `pattern`: None unless every pattern character
+/// appears in it in order, ignoring case. Higher scores are better. Runs of
+/// consecutive characters, matches at the start of words and at the start of
+/// the candidate score more; gaps between matched characters cost.
+///
+/// Every way of matching is considered and the best scoring one wins, so
+/// `api` in `rapid-api` matches the word at the end rather than the letters
+/// scattered through `rapid`.
+#[must_use]
+pub fn find(pattern: &str, candidate: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().map(lower).collect();
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let chars: Vec<char> = candidate.chars().collect();
+    let lowered: Vec<char> = chars.iter().copied().map(lower).collect();
+    let bonuses: Vec<i64> = (0..chars.len()).map(|j| bonus(&chars, j)).collect();
+
+    // For the pattern character being placed, the best score of a match
+    // ending with it at each candidate position and the bonus its run
+    // carries; None where it cannot be placed. `from` keeps, for every
+    // pattern character and position, where the previous character was
+    // placed.
+    let mut previous: Vec<Option<(i64, i64)>> = vec![None; chars.len()];
+    let mut from: Vec<Vec<usize>> = Vec::with_capacity(pattern.len());
+    for (i, p) in pattern.iter().enumerate() {
+        let mut current: Vec<Option<(i64, i64)>> = vec![None; chars.len()];
+        let mut came_from = vec![0; chars.len()];
+        // The best score of a match of the pattern so far ending two or more
+        // positions back, with the gap up to here charged, and where.
+        let mut gapped: Option<(i64, usize)> = None;
+        for j in 0..chars.len() {
+            if j >= 2 {
+                let start = previous[j - 2].map(|(s, _)| (s + GAP_START, j - 2));
+                gapped = match (gapped.map(|(s, k)| (s + GAP_EXTENSION, k)), start) {
+                    (Some(gap), Some(start)) if start.0 > gap.0 => Some(start),
+                    (Some(gap), _) => Some(gap),
+                    (None, start) => start,
+                };
+            }
+            if lowered[j] != *p {
+                continue;
+            }
+            if i == 0 {
+                current[j] = Some((SCORE_MATCH + bonuses[j] * FIRST_CHAR_MULTIPLIER, bonuses[j]));
+                continue;
+            }
+            let run =
+                j.checked_sub(1)
+                    .and_then(|k| Some((previous[k]?, k)))
+                    .map(|((s, carried), k)| {
+                        let bonus = carried.max(bonuses[j]).max(BONUS_CONSECUTIVE);
+                        (s + SCORE_MATCH + bonus, bonus, k)
+                    });
+            let gap = gapped.map(|(s, k)| (s + SCORE_MATCH + bonuses[j], bonuses[j], k));
+            let best = match (run, gap) {
+                (Some(run), Some(gap)) if gap.0 > run.0 => Some(gap),
+                (Some(run), _) => Some(run),
+                (None, gap) => gap,
+            };
+            if let Some((score, carried, k)) = best {
+                current[j] = Some((score, carried));
+                came_from[j] = k;
+            }
+        }
+        from.push(came_from);
+        previous = current;
+    }
+    // The first best end, so ties keep the earliest match.
+    let (mut j, score) = previous
+        .iter()
+        .enumerate()
+        .filter_map(|(j, cell)| Some((j, cell.as_ref()?.0)))
+        .fold(None, |best: Option<(usize, i64)>, (j, s)| match best {
+            Some((_, b)) if b >= s => best,
+            _ => Some((j, s)),
+        })?;
+    let mut positions = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(Match { score, positions })
+}
+
+/// The score of `candidate` against `pattern`; see `find`.
+#[must_use]
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    find(pattern, candidate).map(|m| m.score)
+}
+
+/// Split `text` into runs of characters that are and are not at
+/// `positions` (character positions, as `Match` has them), for
+/// highlighting.
+#[must_use]
+pub fn segments<'a>(text: &'a str, positions: &[usize]) -> Vec<(&'a str, bool)> {
+    let mut segments: Vec<(&str, bool)> = Vec::new();
+    let mut start = 0;
+    let mut matched = false;
+    for (i, (at, _)) in text.char_indices().enumerate() {
+        let here = positions.contains(&i);
+        if at > 0 && here != matched {
+            segments.push((&text[start..at], matched));
+            start = at;
+        }
+        matched = here;
+    }
+    if start < text.len() {
+        segments.push((&text[start..], matched));
+    }
+    segments
+}
+
+/// Where an exact match must be in the candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Anywhere,
+    Start,
+    End,
+    /// The whole candidate.
+    Whole,
+}
+
+/// Match `pattern` as one run of characters in `candidate`, ignoring case,
+/// at `anchor`. Scored like `find` scores a run, at the best place it
+/// occurs.
+#[must_use]
+pub fn find_exact(pattern: &str, candidate: &str, anchor: Anchor) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().map(lower).collect();
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let chars: Vec<char> = candidate.chars().collect();
+    let lowered: Vec<char> = chars.iter().copied().map(lower).collect();
+    let last = chars.len().checked_sub(pattern.len())?;
+    let starts = match anchor {
+        Anchor::Anywhere => 0..=last,
+        Anchor::Start => 0..=0,
+        Anchor::End => last..=last,
+        Anchor::Whole if last == 0 => 0..=0,
+        Anchor::Whole => return None,
+    };
+    starts
+        .filter(|&start| lowered[start..start + pattern.len()] == pattern[..])
+        .map(|start| {
+            let mut carried = bonus(&chars, start);
+            let mut score = SCORE_MATCH + carried * FIRST_CHAR_MULTIPLIER;
+            for j in start + 1..start + pattern.len() {
+                carried = carried.max(bonus(&chars, j)).max(BONUS_CONSECUTIVE);
+                score += SCORE_MATCH + carried;
+            }
+            Match {
+                score,
+                positions: (start..start + pattern.len()).collect(),
+            }
+        })
+        .fold(None, |best: Option<Match>, m| match best {
+            Some(b) if b.score >= m.score => Some(b),
+            _ => Some(m),
+        })
+}
+
+/// The candidates `score` matches, best first. Equal scores put the shorter
+/// candidate first, then fall back to `tie`; candidates scoring 0 (every
+/// one, when there is nothing to match) are ordered by `tie` alone.
+#[must_use]
+pub fn rank<'a, I, S, F>(candidates: I, score: S, tie: F) -> Vec<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+    S: Fn(&str) -> Option<i64>,
+    F: Fn(&str, &str) -> Ordering,
+{
+    let mut matches: Vec<(&str, i64)> = candidates
+        .into_iter()
+        .filter_map(|c| Some((c, score(c)?)))
+        .collect();
+    matches.sort_by(|(a, a_score), (b, b_score)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| {
+                if *a_score == 0 {
+                    Ordering::Equal
+                } else {
+                    a.chars().count().cmp(&b.chars().count())
+                }
+            })
+            .then_with(|| tie(a, b))
+    });
+    matches.into_iter().map(|(c, _)| c).collect()
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// What matching the character at `j` is worth beyond the match itself.
+fn bonus(chars: &[char], j: usize) -> i64 {
+    let Some(before) = j.checked_sub(1).map(|k| chars[k]) else {
+        return BONUS_PREFIX;
+    };
+    let c = chars[j];
+    if !c.is_alphanumeric() {
+        0
+    } else if !before.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if before.is_lowercase() && c.is_uppercase() {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ranked<'a>(pattern: &str, names: &[&'a str]) -> Vec<&'a str> {
+        rank(names.iter().copied(), |c| score(pattern, c), str::cmp)
+    }
+
+    #[test]
+    fn every_character_must_match_in_order() {
+        assert!(score("api", "api").is_some());
+        assert!(score("API", "my-api").is_some());
+        assert!(score("aip", "api").is_none());
+        assert!(score("apis", "api").is_none());
+        assert_eq!(score("", "anything"), Some(0));
+        assert!(score("x", "").is_none());
+    }
+
+    // Letters starting the name and words outscore a run inside a word, so
+    // `capital` lists last; the README says as much.
+    #[test]
+    fn exact_and_prefix_matches_beat_scattered_letters() {
+        assert_eq!(
+            ranked(
+                "api",
+                &[
+                    "a-big-pile-of-stuff",
+                    "capital",
+                    "rapid-api",
+                    "api-gateway",
+                    "api"
+                ]
+            ),
+            vec![
+                "api",
+                "api-gateway",
+                "rapid-api",
+                "a-big-pile-of-stuff",
+                "capital"
+            ]
+        );
+    }
+
+    #[test]
+    fn word_boundaries_beat_letters_inside_words() {
+        assert_eq!(
+            ranked("ps", &["perhaps", "project-switcher"]),
+            vec!["project-switcher", "perhaps"]
+        );
+        assert_eq!(
+            ranked("ds", &["dotfiles", "DevServer", "ad-hoc-scripts"]),
+            vec!["DevServer", "dotfiles", "ad-hoc-scripts"]
+        );
+    }
+
+    #[test]
+    fn contiguous_runs_beat_gaps() {
+        assert_eq!(
+            ranked("web", &["w-e-b", "my-webapp", "wide-eb"]),
+            vec!["my-webapp", "w-e-b", "wide-eb"]
+        );
+    }
+
+    #[test]
+    fn equal_scores_prefer_the_shorter_candidate() {
+        assert_eq!(
+            ranked("notes", &["notes-archive", "notes", "notes-2024"]),
+            vec!["notes", "notes-2024", "notes-archive"]
+        );
+    }
+
+    #[test]
+    fn without_a_pattern_the_tie_break_decides() {
+        assert_eq!(
+            rank(
+                ["b", "a", "ccc"].iter().copied(),
+                |c| score("", c),
+                |a, b| b.cmp(a)
+            ),
+            vec!["ccc", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn positions_are_those_of_the_best_match() {
+        assert_eq!(find("api", "rapid-api").unwrap().positions, vec![6, 7, 8]);
+        assert_eq!(
+            find("ps", "project-switcher").unwrap().positions,
+            vec![0, 8]
+        );
+        assert_eq!(find("DS", "DevServer").unwrap().positions, vec![0, 3]);
+        assert_eq!(find("aaa", "aaaa").unwrap().positions, vec![0, 1, 2]);
+        assert!(find("", "x").unwrap().positions.is_empty());
+        assert_eq!(
+            find("api", "api").unwrap().score,
+            score("api", "api").unwrap()
+        );
+    }
+
+    #[test]
+    fn multi_byte_positions_count_characters() {
+        let m = find("éé", "café-été").unwrap();
+        assert_eq!(m.positions, vec![5, 7]);
+        assert_eq!(
+            segments("café-été", &m.positions),
+            vec![("café-", false), ("é", true), ("t", false), ("é", true)]
+        );
+    }
+
+    #[test]
+    fn segments_split_at_match_edges() {
+        assert_eq!(
+            segments("rapid-api", &[6, 7, 8]),
+            vec![("rapid-", false), ("api", true)]
+        );
+        assert_eq!(
+            segments("api", &[0, 2]),
+            vec![("a", true), ("p", false), ("i", true)]
+        );
+        assert_eq!(segments("api", &[]), vec![("api", false)]);
+        assert!(segments("", &[]).is_empty());
+    }
+
+    #[test]
+    fn multi_byte_names_are_matched_by_character() {
+        assert_eq!(
+            ranked("café", &["le-café", "Café-Tools", "cafeteria"]),
+            vec!["Café-Tools", "le-café"]
+        );
+    }
+
+    #[test]
+    fn exact_matches_are_runs_at_their_anchor() {
+        let m = find_exact("API", "rapid-api", Anchor::Anywhere).unwrap();
+        assert_eq!(m.positions, vec![6, 7, 8]);
+        assert!(find_exact("api", "rapid-api", Anchor::Start).is_none());
+        assert_eq!(
+            find_exact("api", "rapid-api", Anchor::End)
+                .unwrap()
+                .positions,
+            vec![6, 7, 8]
+        );
+        assert!(find_exact("api", "rapid-api", Anchor::Whole).is_none());
+        assert!(find_exact("Api", "api", Anchor::Whole).is_some());
+        assert!(find_exact("aip", "api", Anchor::Anywhere).is_none());
+        assert!(find_exact("apis", "api", Anchor::Anywhere).is_none());
+        assert_eq!(
+            find_exact("é", "été", Anchor::End).unwrap().positions,
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn an_exact_run_scores_as_the_fuzzy_match_of_it() {
+        for (pattern, candidate) in [("api", "rapid-api"), ("web", "my-webapp"), ("ab", "xaby")] {
+            assert_eq!(
+                find_exact(pattern, candidate, Anchor::Anywhere),
+                find(pattern, candidate)
+            );
+        }
+    }
+}
@@ -1,2 +1,14 @@
+pub mod cache;
 pub mod core;
+pub mod discovery;
+pub mod editors;
+pub mod exclude;
+pub mod fuzzy;
+pub mod markers;
+pub mod remote;
+pub mod source;
 pub mod stack;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod worktree;
+pub mod zoxide;
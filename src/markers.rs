@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+
+/// The version control system a project is checked out with, identified by
+/// the marker its working copy contains.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Vcs {
+    #[default]
+    Git,
+    Jujutsu,
+    Mercurial,
+    Pijul,
+    Fossil,
+}
+
+/// Every VCS the plugin recognises, in the order their markers are searched.
+pub const ALL_VCS: [Vcs; 5] = [
+    Vcs::Git,
+    Vcs::Jujutsu,
+    Vcs::Mercurial,
+    Vcs::Pijul,
+    Vcs::Fossil,
+];
+
+impl Vcs {
+    /// The short name shown in the list and stored in the project cache.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Vcs::Git => "git",
+            Vcs::Jujutsu => "jj",
+            Vcs::Mercurial => "hg",
+            Vcs::Pijul => "pijul",
+            Vcs::Fossil => "fossil",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Vcs> {
+        ALL_VCS.iter().copied().find(|v| v.name() == name)
+    }
+
+    /// The entries whose presence marks a working copy. Fossil checkouts
+    /// carry `.fslckout`, or `_FOSSIL_` on Windows-created checkouts.
+    #[must_use]
+    pub fn markers(self) -> &'static [&'static str] {
+        match self {
+            Vcs::Git => &[".git"],
+            Vcs::Jujutsu => &[".jj"],
+            Vcs::Mercurial => &[".hg"],
+            Vcs::Pijul => &[".pijul"],
+            Vcs::Fossil => &[".fslckout", "_FOSSIL_"],
+        }
+    }
+
+    #[must_use]
+    pub fn from_marker(marker: &str) -> Option<Vcs> {
+        ALL_VCS
+            .iter()
+            .copied()
+            .find(|v| v.markers().contains(&marker))
+    }
+
+    /// Which VCS a directory carrying several markers is listed as. A
+    /// colocated jj repository also has a `.git`, and jj is what the user
+    /// drives it with.
+    #[must_use]
+    pub fn prefer(self, other: Vcs) -> Vcs {
+        if other == Vcs::Jujutsu {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// The entry names discovery looks for: VCS markers, or files such as
+/// `Cargo.toml` that mark a project which is not a repository (yet).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Markers {
+    names: Vec<String>,
+    nested: bool,
+}
+
+impl Default for Markers {
+    fn default() -> Markers {
+        Markers {
+            names: ALL_VCS
+                .iter()
+                .flat_map(|v| v.markers())
+                .map(|m| (*m).to_string())
+                .collect(),
+            nested: false,
+        }
+    }
+}
+
+impl Markers {
+    /// Read the `markers` setting: entry names separated by `:`, e.g.
+    /// `.git:Cargo.toml:flake.nix`. Names containing `/` are ignored; when
+    /// nothing usable is configured every VCS marker is searched.
+    /// `nested_markers "true"` keeps projects found by a marker file inside
+    /// another project, which are otherwise dropped.
+    #[must_use]
+    pub fn from_config(config: &BTreeMap<String, String>) -> Markers {
+        let mut names: Vec<String> = Vec::new();
+        for name in config
+            .get("markers")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(':')
+        {
+            let name = name.trim();
+            if !name.is_empty() && !name.contains('/') && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+        let nested = config
+            .get("nested_markers")
+            .is_some_and(|v| v.trim() == "true");
+        if names.is_empty() {
+            Markers {
+                nested,
+                ..Markers::default()
+            }
+        } else {
+            Markers { names, nested }
+        }
+    }
+
+    #[must_use]
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// Whether projects nested inside another project are kept when only a
+    /// marker file identifies them.
+    #[must_use]
+    pub fn nested(&self) -> bool {
+        self.nested
+    }
+
+    /// An fd regex matching exactly the marker names.
+    #[must_use]
+    pub fn fd_pattern(&self) -> String {
+        let escaped: Vec<String> = self.names.iter().map(|n| regex::escape(n)).collect();
+        if escaped.len() == 1 {
+            format!("^{}$", escaped[0])
+        } else {
+            format!("^({})$", escaped.join("|"))
+        }
+    }
+
+    /// `find` arguments matching any of the marker names.
+    #[must_use]
+    pub fn find_args(&self) -> Vec<String> {
+        let mut args = vec![String::from("(")];
+        for (i, name) in self.names.iter().enumerate() {
+            if i > 0 {
+                args.push(String::from("-o"));
+            }
+            args.push(String::from("-name"));
+            args.push(name.clone());
+        }
+        args.push(String::from(")"));
+        args
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(markers: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([(String::from("markers"), String::from(markers))])
+    }
+
+    #[test]
+    fn vcs_is_identified_by_marker() {
+        assert_eq!(Vcs::from_marker(".git"), Some(Vcs::Git));
+        assert_eq!(Vcs::from_marker(".jj"), Some(Vcs::Jujutsu));
+        assert_eq!(Vcs::from_marker(".hg"), Some(Vcs::Mercurial));
+        assert_eq!(Vcs::from_marker(".pijul"), Some(Vcs::Pijul));
+        assert_eq!(Vcs::from_marker("_FOSSIL_"), Some(Vcs::Fossil));
+        assert_eq!(Vcs::from_marker(".fslckout"), Some(Vcs::Fossil));
+        assert_eq!(Vcs::from_marker(".svn"), None);
+    }
+
+    #[test]
+    fn vcs_names_round_trip() {
+        for vcs in ALL_VCS {
+            assert_eq!(Vcs::from_name(vcs.name()), Some(vcs));
+        }
+    }
+
+    #[test]
+    fn colocated_jj_wins_over_git() {
+        assert_eq!(Vcs::Git.prefer(Vcs::Jujutsu), Vcs::Jujutsu);
+        assert_eq!(Vcs::Jujutsu.prefer(Vcs::Git), Vcs::Jujutsu);
+        assert_eq!(Vcs::Git.prefer(Vcs::Mercurial), Vcs::Git);
+    }
+
+    #[test]
+    fn markers_default_to_every_vcs() {
+        assert_eq!(
+            Markers::from_config(&BTreeMap::new()).names(),
+            [".git", ".jj", ".hg", ".pijul", ".fslckout", "_FOSSIL_"]
+        );
+    }
+
+    #[test]
+    fn markers_are_configurable() {
+        let markers = Markers::from_config(&config(".git: .hg :.git:Cargo.toml"));
+        assert_eq!(markers.names(), [".git", ".hg", "Cargo.toml"]);
+        assert!(markers.contains("Cargo.toml"));
+        assert!(!markers.contains(".jj"));
+        assert!(!markers.nested());
+        assert_eq!(Markers::from_config(&config(" : a/b")), Markers::default());
+    }
+
+    #[test]
+    fn nested_markers_are_opt_in() {
+        let mut config = config("Cargo.toml");
+        config.insert(String::from("nested_markers"), String::from("true"));
+        assert!(Markers::from_config(&config).nested());
+        config.remove("markers");
+        let markers = Markers::from_config(&config);
+        assert!(markers.nested());
+        assert_eq!(markers.names(), Markers::default().names());
+    }
+
+    #[test]
+    fn fd_pattern_escapes_and_alternates() {
+        assert_eq!(
+            Markers::from_config(&config(".git")).fd_pattern(),
+            "^\\.git$"
+        );
+        assert_eq!(
+            Markers::from_config(&config(".git:_FOSSIL_:Cargo.toml")).fd_pattern(),
+            "^(\\.git|_FOSSIL_|Cargo\\.toml)$"
+        );
+    }
+
+    #[test]
+    fn find_args_alternate_names() {
+        assert_eq!(
+            Markers::from_config(&config(".git:.hg")).find_args(),
+            vec!["(", "-name", ".git", "-o", "-name", ".hg", ")"]
+        );
+    }
+}
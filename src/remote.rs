@@ -0,0 +1,166 @@
+use std::path::Path;
+
+/// The URL of the `origin` remote in the contents of a repository's
+/// `.git/config`. Section and key names are case-insensitive, as git treats
+/// them; the remote's name is not.
+#[must_use]
+pub fn origin_url(config: &str) -> Option<String> {
+    let mut in_origin = false;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let section = line.trim_start_matches('[').split(']').next()?;
+            in_origin = match section.split_once(char::is_whitespace) {
+                Some((kind, name)) => {
+                    kind.eq_ignore_ascii_case("remote") && name.trim() == "\"origin\""
+                }
+                None => section.eq_ignore_ascii_case("remote.origin"),
+            };
+            continue;
+        }
+        if !in_origin {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if key.trim().eq_ignore_ascii_case("url") {
+            let value = value.split([';', '#']).next().unwrap_or_default().trim();
+            let value = value.trim_matches('"');
+            if !value.is_empty() {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// The `org/repo` name of a remote URL: the last two components of its path,
+/// without `.git`. Handles `https://` and `ssh://` URLs and scp-style
+/// `git@host:org/repo`. None for local paths and paths too short to have an
+/// org.
+#[must_use]
+pub fn remote_name(url: &str) -> Option<String> {
+    let url = url.trim();
+    let path = if let Some((scheme, rest)) = url.split_once("://") {
+        if scheme == "file" {
+            return None;
+        }
+        // Drop `[user@]host[:port]`.
+        rest.split_once('/')?.1
+    } else {
+        let (host, path) = url.split_once(':')?;
+        if host.is_empty() || host.contains('/') {
+            return None;
+        }
+        path
+    };
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    match parts[..] {
+        [.., org, repo] if !org.starts_with('~') => Some(format!("{org}/{repo}")),
+        _ => None,
+    }
+}
+
+/// The `org/repo` name of the origin remote of the git repository at
+/// `path`, reading its `.git/config` below `mount`, which the host has
+/// mapped to `/`. None when it cannot be read or has no usable origin.
+#[must_use]
+pub fn read_remote_name(mount: &Path, path: &str) -> Option<String> {
+    let config = mount.join(path.trim_start_matches('/')).join(".git/config");
+    let config = std::fs::read_to_string(config).ok()?;
+    remote_name(&origin_url(&config)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::scratch;
+
+    #[test]
+    fn origin_is_read_from_its_section() {
+        let config = "[core]\n\trepositoryformatversion = 0\n[remote \"upstream\"]\n\turl = https://github.com/rust-lang/rust\n[remote \"origin\"]\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n\turl = git@github.com:me/rust.git\n[branch \"main\"]\n\tremote = origin\n";
+        assert_eq!(
+            origin_url(config),
+            Some(String::from("git@github.com:me/rust.git"))
+        );
+    }
+
+    #[test]
+    fn origin_tolerates_spacing_case_and_comments() {
+        assert_eq!(
+            origin_url("[Remote \"origin\"]\nURL=\"https://h/o/r\" # mine\n"),
+            Some(String::from("https://h/o/r"))
+        );
+        assert_eq!(
+            origin_url("[remote.origin]\n  url = ssh://h/o/r\n"),
+            Some(String::from("ssh://h/o/r"))
+        );
+    }
+
+    #[test]
+    fn missing_origin_is_none() {
+        assert_eq!(origin_url("[core]\n\tbare = false\n"), None);
+        assert_eq!(
+            origin_url("[remote \"fork\"]\n\turl = https://h/o/r\n"),
+            None
+        );
+        assert_eq!(origin_url("[remote \"origin\"]\n\tfetch = x\n"), None);
+        assert_eq!(origin_url(""), None);
+    }
+
+    #[test]
+    fn remote_names_are_org_and_repo() {
+        for url in [
+            "https://github.com/leapingfrogs/zellij-project-switcher.git",
+            "https://github.com/leapingfrogs/zellij-project-switcher/",
+            "git@github.com:leapingfrogs/zellij-project-switcher.git",
+            "ssh://git@github.com:22/leapingfrogs/zellij-project-switcher",
+            "github.com:leapingfrogs/zellij-project-switcher",
+        ] {
+            assert_eq!(
+                remote_name(url),
+                Some(String::from("leapingfrogs/zellij-project-switcher")),
+                "{url}"
+            );
+        }
+        assert_eq!(
+            remote_name("https://gitlab.com/group/subgroup/app.git"),
+            Some(String::from("subgroup/app"))
+        );
+    }
+
+    #[test]
+    fn local_and_short_remotes_have_no_name() {
+        assert_eq!(remote_name("/srv/git/app.git"), None);
+        assert_eq!(remote_name("../app"), None);
+        assert_eq!(remote_name("file:///srv/git/org/app.git"), None);
+        assert_eq!(remote_name("git@host:app.git"), None);
+        assert_eq!(remote_name("ssh://host/~me/app.git"), None);
+        assert_eq!(remote_name(""), None);
+    }
+
+    #[test]
+    fn remote_names_are_read_from_the_host() {
+        let mount = scratch("zps-remote-test", &["src/app/.git", "src/local/.git"]);
+        std::fs::write(
+            mount.join("src/app/.git/config"),
+            "[remote \"origin\"]\n\turl = git@github.com:acme/app.git\n",
+        )
+        .unwrap();
+        std::fs::write(
+            mount.join("src/local/.git/config"),
+            "[core]\n\tbare = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_remote_name(&mount, "/src/app"),
+            Some(String::from("acme/app"))
+        );
+        assert_eq!(read_remote_name(&mount, "/src/local"), None);
+        assert_eq!(read_remote_name(&mount, "/src/gone"), None);
+        std::fs::remove_dir_all(&mount).ok();
+    }
+}
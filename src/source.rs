@@ -0,0 +1,593 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::core::{self, discovery_command, expand_path, normalise_path, Root};
+use crate::discovery::Backend;
+use crate::exclude::Exclude;
+use crate::markers::{Markers, Vcs};
+
+/// A project as a source lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceProject {
+    pub path: String,
+    /// The name the source gives it; None leaves it to the `naming` setting.
+    pub name: Option<String>,
+    pub vcs: Option<Vcs>,
+}
+
+impl SourceProject {
+    #[must_use]
+    pub fn new(path: &str) -> SourceProject {
+        SourceProject {
+            path: path.to_string(),
+            name: None,
+            vcs: None,
+        }
+    }
+
+    #[must_use]
+    pub fn named(name: &str, path: &str) -> SourceProject {
+        SourceProject {
+            name: Some(name.to_string()),
+            ..SourceProject::new(path)
+        }
+    }
+}
+
+/// A command a source needs run, and the context zellij echoes back with its
+/// output in `RunCommandResult`.
+pub type SourceCommand = (Vec<String>, BTreeMap<String, String>);
+
+/// Somewhere projects are listed from. A plugin cannot wait on a command, so
+/// a source that needs one hands it out from `commands` and turns the output
+/// into projects in `parse` once the host sends it back; a source that knows
+/// its projects up front returns them from `load`.
+pub trait ProjectSource {
+    /// Identifies the source in its commands' context and in the merged list.
+    fn name(&self) -> &'static str;
+
+    /// Which source's details win when two list the same path, and which
+    /// keeps a name two give to different paths; higher wins.
+    fn precedence(&self) -> u8;
+
+    fn load(&self) -> Vec<SourceProject> {
+        Vec::new()
+    }
+
+    fn commands(&self) -> Vec<SourceCommand> {
+        Vec::new()
+    }
+
+    /// The projects in the output of the command `context` came with.
+    fn parse(&self, _context: &BTreeMap<String, String>, _output: &str) -> Vec<SourceProject> {
+        Vec::new()
+    }
+}
+
+/// Searching the roots with fd (or fdfind or find; see
+/// `core::discovery_command`). Each root's output, whether a command printed
+/// it or the native backend walked the root, goes to
+/// `SourceRegistry::receive` with the root in the context's `root`. A `.git`
+/// file in it may be a repository or a linked worktree, which only reading
+/// it tells; the caller hands the repositories over with
+/// `SourceRegistry::list` (see `core::parse_worktree_lines`).
+#[derive(Debug, Clone, Default)]
+pub struct FdSource {
+    pub roots: Vec<Root>,
+    pub backend: Backend,
+    pub markers: Markers,
+    pub exclude: Exclude,
+}
+
+impl FdSource {
+    /// `roots` are the configured roots with their paths expanded.
+    #[must_use]
+    pub fn from_config(
+        config: &BTreeMap<String, String>,
+        roots: &[Root],
+        backend: Backend,
+    ) -> FdSource {
+        FdSource {
+            roots: roots.to_vec(),
+            backend,
+            markers: Markers::from_config(config),
+            exclude: Exclude::from_config(config),
+        }
+    }
+
+    /// Drop the entries of `root`'s discovery output that its options and
+    /// the `exclude` setting leave out; the search itself cannot always
+    /// skip them.
+    #[must_use]
+    pub fn filter_output(&self, root: &str, output: &str) -> String {
+        let output = self.exclude.filter_output(root, output);
+        match self.roots.iter().find(|r| r.path == root) {
+            Some(root) => root.filter_output(&output),
+            None => output,
+        }
+    }
+
+    /// The projects in `root`'s discovery output, filtered as by
+    /// `filter_output`.
+    #[must_use]
+    pub fn projects(&self, root: &str, output: &str) -> Vec<SourceProject> {
+        core::parse_lines(&self.filter_output(root, output), &self.markers)
+            .into_iter()
+            .map(|(path, vcs)| SourceProject {
+                vcs,
+                ..SourceProject::new(&path)
+            })
+            .collect()
+    }
+}
+
+impl ProjectSource for FdSource {
+    fn name(&self) -> &'static str {
+        "fd"
+    }
+
+    fn precedence(&self) -> u8 {
+        0
+    }
+
+    fn commands(&self) -> Vec<SourceCommand> {
+        if self.backend == Backend::Native {
+            return Vec::new();
+        }
+        self.roots
+            .iter()
+            .map(|root| discovery_command(root, self.backend, &self.markers, &self.exclude))
+            .collect()
+    }
+
+    fn parse(&self, context: &BTreeMap<String, String>, output: &str) -> Vec<SourceProject> {
+        let root = context.get("root").map(String::as_str).unwrap_or_default();
+        self.projects(root, output)
+    }
+}
+
+/// The `pinned` setting's projects, name to path with the paths expanded;
+/// they win over every other source.
+#[derive(Debug, Clone)]
+pub struct PinnedSource {
+    pub projects: BTreeMap<String, String>,
+}
+
+impl ProjectSource for PinnedSource {
+    fn name(&self) -> &'static str {
+        "pinned"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn load(&self) -> Vec<SourceProject> {
+        self.projects
+            .iter()
+            .map(|(name, path)| SourceProject::named(name, path))
+            .collect()
+    }
+}
+
+/// Directories the plugin finds without a command of its own, such as the
+/// editors' recent folders; it hands them over with `SourceRegistry::list`.
+#[derive(Debug, Clone)]
+pub struct FoundSource {
+    pub name: &'static str,
+}
+
+impl ProjectSource for FoundSource {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn precedence(&self) -> u8 {
+        0
+    }
+}
+
+/// The `projects_file` setting: a plain-text file listing one project per
+/// line, as a path or `name=path`. Blank lines and lines starting with `#`
+/// are skipped, and paths are expanded like configured ones.
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    pub path: String,
+    pub env: BTreeMap<String, String>,
+}
+
+impl FileSource {
+    /// None unless `projects_file` is set. `path` is the setting with its
+    /// path expanded.
+    #[must_use]
+    pub fn from_config(
+        config: &BTreeMap<String, String>,
+        path: Option<String>,
+        env: &BTreeMap<String, String>,
+    ) -> Option<FileSource> {
+        config.get("projects_file")?;
+        Some(FileSource {
+            path: path?,
+            env: env.clone(),
+        })
+    }
+}
+
+impl ProjectSource for FileSource {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    /// Its context names the file in `path`, for reporting it unreadable.
+    fn commands(&self) -> Vec<SourceCommand> {
+        vec![(
+            vec![String::from("cat"), self.path.clone()],
+            BTreeMap::from([(String::from("path"), self.path.clone())]),
+        )]
+    }
+
+    fn parse(&self, _context: &BTreeMap<String, String>, output: &str) -> Vec<SourceProject> {
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (name, path) = match line.split_once('=') {
+                    Some((name, path)) => (Some(name.trim()).filter(|n| !n.is_empty()), path),
+                    None => (None, line),
+                };
+                let path = expand_path(path.trim(), &self.env).ok()?;
+                if !path.starts_with('/') {
+                    return None;
+                }
+                let path = normalise_path(&path);
+                Some(match name {
+                    Some(name) => SourceProject::named(name, &path),
+                    None => SourceProject::new(&path),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A project in the merged list and the source whose entry won.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listed {
+    pub name: Option<String>,
+    pub vcs: Option<Vcs>,
+    pub source: &'static str,
+}
+
+/// The registered sources and what each has listed so far.
+///
+/// When several sources list the same path the one with the highest
+/// precedence wins, the earliest registered among equals, and details it
+/// leaves out (a name, the VCS) are taken from the others in the same order.
+/// A name given to several paths stays with the winning source's path; the
+/// others fall back to the `naming` setting.
+#[derive(Default)]
+pub struct SourceRegistry {
+    sources: Vec<Box<dyn ProjectSource>>,
+    // Each source's projects, by the context of the command that listed
+    // them; an empty context holds what `load` returned.
+    found: BTreeMap<(usize, BTreeMap<String, String>), Vec<SourceProject>>,
+}
+
+impl SourceRegistry {
+    /// Add a source, or replace the one registered under the same name,
+    /// keeping what that one listed.
+    pub fn register<S: ProjectSource + 'static>(&mut self, source: S) {
+        match self.sources.iter().position(|s| s.name() == source.name()) {
+            Some(i) => self.sources[i] = Box::new(source),
+            None => self.sources.push(Box::new(source)),
+        }
+    }
+
+    /// Forget every source and what they listed.
+    pub fn clear(&mut self) {
+        self.sources.clear();
+        self.found.clear();
+    }
+
+    /// Take the projects of sources that know them up front.
+    pub fn load(&mut self) {
+        for (i, source) in self.sources.iter().enumerate() {
+            self.found.insert((i, BTreeMap::new()), source.load());
+        }
+    }
+
+    /// Every source's commands. Their context names the source in `source`
+    /// and, unless the source set one, has `command` `project_source`.
+    #[must_use]
+    pub fn commands(&self) -> Vec<SourceCommand> {
+        let mut commands = Vec::new();
+        for source in &self.sources {
+            for (cmd, mut context) in source.commands() {
+                context.insert(String::from("source"), source.name().to_string());
+                context
+                    .entry(String::from("command"))
+                    .or_insert_with(|| String::from("project_source"));
+                commands.push((cmd, context));
+            }
+        }
+        commands
+    }
+
+    /// A source's command has finished: replace what that command listed
+    /// before with what `output` lists, and return it. None when `context`
+    /// names no registered source.
+    pub fn receive(
+        &mut self,
+        context: &BTreeMap<String, String>,
+        output: &str,
+    ) -> Option<&[SourceProject]> {
+        let name = context.get("source")?;
+        let i = self.sources.iter().position(|s| s.name() == name)?;
+        let projects = self.sources[i].parse(context, output);
+        Some(
+            self.found
+                .entry((i, context.clone()))
+                .insert_entry(projects)
+                .into_mut(),
+        )
+    }
+
+    /// Replace what the caller listed for the source named `source` before
+    /// with `projects`, found without the source's commands. False when no
+    /// source by that name is registered.
+    pub fn list(&mut self, source: &str, projects: Vec<SourceProject>) -> bool {
+        let Some(i) = self.sources.iter().position(|s| s.name() == source) else {
+            return false;
+        };
+        let context = BTreeMap::from([(String::from("source"), source.to_string())]);
+        self.found.insert((i, context), projects);
+        true
+    }
+
+    /// Every listed project by path, merged by precedence.
+    #[must_use]
+    pub fn merged(&self) -> BTreeMap<String, Listed> {
+        let mut entries: Vec<(usize, &SourceProject)> = self
+            .found
+            .iter()
+            .flat_map(|((i, _), projects)| projects.iter().map(move |p| (*i, p)))
+            .collect();
+        let sources = &self.sources;
+        // Stable, so a source's own order is kept among its entries.
+        entries.sort_by_key(|(i, _)| (std::cmp::Reverse(sources[*i].precedence()), *i));
+
+        let mut merged: BTreeMap<String, Listed> = BTreeMap::new();
+        let mut claimed: BTreeSet<String> = BTreeSet::new();
+        for (i, project) in &entries {
+            let listed = merged
+                .entry(project.path.clone())
+                .or_insert_with(|| Listed {
+                    name: None,
+                    vcs: None,
+                    source: sources[*i].name(),
+                });
+            if listed.vcs.is_none() {
+                listed.vcs = project.vcs;
+            }
+            if listed.name.is_none() {
+                if let Some(name) = project.name.as_ref().filter(|n| !claimed.contains(*n)) {
+                    claimed.insert(name.clone());
+                    listed.name = Some(name.clone());
+                }
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    fn fd_source() -> FdSource {
+        let roots = core::parse_roots(&config(&[("roots", "/src[hidden=false]:/work[depth=2]")]));
+        let config = config(&[("exclude", "node_modules"), ("markers", ".git:Cargo.toml")]);
+        FdSource::from_config(&config, &roots, Backend::Fd)
+    }
+
+    #[test]
+    fn fd_source_runs_one_command_per_root() {
+        let commands = fd_source().commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0.first().map(String::as_str), Some("fd"));
+        assert_eq!(commands[0].0.last().map(String::as_str), Some("/src"));
+        assert_eq!(commands[1].1["root"], "/work");
+        assert!(commands[1].0.contains(&String::from("--max-depth=3")));
+        let native = FdSource {
+            backend: Backend::Native,
+            ..fd_source()
+        };
+        assert!(native.commands().is_empty());
+    }
+
+    #[test]
+    fn fd_source_parses_and_filters_a_roots_output() {
+        let context = config(&[("root", "/src")]);
+        let output = "/src/api/.git/\0/src/node_modules/x/.git/\0/src/.hidden/y/Cargo.toml\0/src/tool/Cargo.toml\0/src/wt/.git\0";
+        assert_eq!(
+            fd_source().parse(&context, output),
+            vec![
+                SourceProject {
+                    vcs: Some(Vcs::Git),
+                    ..SourceProject::new("/src/api")
+                },
+                SourceProject::new("/src/tool"),
+            ]
+        );
+    }
+
+    #[test]
+    fn pinned_source_lists_its_projects_up_front() {
+        let pinned = PinnedSource {
+            projects: BTreeMap::from([(String::from("home"), String::from("/home/me"))]),
+        };
+        assert_eq!(
+            pinned.load(),
+            vec![SourceProject::named("home", "/home/me")]
+        );
+        assert!(pinned.commands().is_empty());
+    }
+
+    #[test]
+    fn file_source_reads_paths_and_named_paths() {
+        let env = config(&[("HOME", "/home/me")]);
+        let file = FileSource::from_config(
+            &config(&[("projects_file", "~/projects.txt")]),
+            Some(String::from("/home/me/projects.txt")),
+            &env,
+        )
+        .unwrap();
+        assert_eq!(
+            file.commands(),
+            vec![(
+                vec![String::from("cat"), String::from("/home/me/projects.txt")],
+                config(&[("path", "/home/me/projects.txt")])
+            )]
+        );
+        let contents =
+            "# work\n/srv/api/\n\n  notes = ~/notes  \n=~/blank-name\nrelative/path\n$UNSET/x\n";
+        assert_eq!(
+            file.parse(&BTreeMap::new(), contents),
+            vec![
+                SourceProject::new("/srv/api"),
+                SourceProject::named("notes", "/home/me/notes"),
+                SourceProject::new("/home/me/blank-name"),
+            ]
+        );
+        assert!(FileSource::from_config(&BTreeMap::new(), None, &env).is_none());
+    }
+
+    fn registry() -> SourceRegistry {
+        let mut registry = SourceRegistry::default();
+        registry.register(fd_source());
+        registry.register(FileSource {
+            path: String::from("/p.txt"),
+            env: BTreeMap::new(),
+        });
+        registry.register(PinnedSource {
+            projects: BTreeMap::from([
+                (String::from("api"), String::from("/srv/api")),
+                (String::from("home"), String::from("/home/me")),
+            ]),
+        });
+        registry
+    }
+
+    #[test]
+    fn registry_tags_commands_with_their_source() {
+        let commands = registry().commands();
+        let sources: Vec<(&str, &str)> = commands
+            .iter()
+            .map(|(_, c)| (c["source"].as_str(), c["command"].as_str()))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("fd", "refresh_projects"),
+                ("fd", "refresh_projects"),
+                ("file", "project_source"),
+            ]
+        );
+    }
+
+    #[test]
+    fn registry_merges_by_precedence() {
+        let mut registry = registry();
+        registry.load();
+        let (_, fd_context) = registry.commands().remove(0);
+        assert_eq!(
+            registry
+                .receive(&fd_context, "/src/api/.git/\0/src/web/.git/\0")
+                .map(<[SourceProject]>::len),
+            Some(2)
+        );
+        let file_context = config(&[("source", "file")]);
+        assert!(registry
+            .receive(&file_context, "/src/api\napi=/src/web\nme=/home/me\n")
+            .is_some());
+        assert!(registry
+            .receive(&config(&[("source", "gone")]), "/x\n")
+            .is_none());
+        assert!(registry.receive(&BTreeMap::new(), "/x\n").is_none());
+
+        let merged = registry.merged();
+        let listed = |path: &str| {
+            let l = &merged[path];
+            (l.name.as_deref(), l.vcs, l.source)
+        };
+        assert_eq!(merged.len(), 4);
+        // Pinned wins the path and its name; the file's own name for the
+        // same path is not needed.
+        assert_eq!(listed("/home/me"), (Some("home"), None, "pinned"));
+        assert_eq!(listed("/srv/api"), (Some("api"), None, "pinned"));
+        // The file wins over fd but keeps the VCS only fd knows; the name
+        // it asks for is pinned's, so it is left to `naming`.
+        assert_eq!(listed("/src/web"), (None, Some(Vcs::Git), "file"));
+        assert_eq!(listed("/src/api"), (None, Some(Vcs::Git), "file"));
+    }
+
+    #[test]
+    fn listed_finds_merge_with_the_other_sources() {
+        let mut registry = registry();
+        registry.register(FoundSource { name: "editors" });
+        registry.load();
+        assert!(registry.list(
+            "fd",
+            vec![SourceProject {
+                vcs: Some(Vcs::Git),
+                ..SourceProject::new("/srv/api")
+            }]
+        ));
+        assert!(registry.list(
+            "editors",
+            vec![SourceProject::new("/home/me"), SourceProject::new("/notes")]
+        ));
+        assert!(!registry.list("zoxide", vec![SourceProject::new("/x")]));
+
+        let merged = registry.merged();
+        assert_eq!(merged.len(), 3);
+        // Found under a pinned name, the path is listed once, by that name.
+        assert_eq!(merged["/srv/api"].name.as_deref(), Some("api"));
+        assert_eq!(merged["/srv/api"].vcs, Some(Vcs::Git));
+        assert_eq!(merged["/notes"].source, "editors");
+    }
+
+    #[test]
+    fn registering_a_name_again_replaces_that_source() {
+        let mut registry = registry();
+        registry.list("fd", vec![SourceProject::new("/src/api")]);
+        registry.register(FdSource {
+            backend: Backend::Native,
+            ..fd_source()
+        });
+        assert!(registry.commands().iter().all(|(_, c)| c["source"] != "fd"));
+        assert!(registry.merged().contains_key("/src/api"));
+    }
+
+    #[test]
+    fn receiving_again_replaces_a_commands_projects() {
+        let mut registry = registry();
+        let context = config(&[("source", "file")]);
+        registry.receive(&context, "/a\n/b\n");
+        registry.receive(&context, "/b\n");
+        assert_eq!(registry.merged().keys().collect::<Vec<_>>(), vec!["/b"]);
+        registry.clear();
+        assert!(registry.merged().is_empty());
+        assert!(registry.commands().is_empty());
+    }
+}
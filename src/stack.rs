@@ -148,7 +148,39 @@ pub fn claim_toggle_slot(path: &Path) -> bool {
     true
 }
 
-fn tmp_path(path: &Path) -> PathBuf {
+/// `field` with backslashes, line breaks and tabs written as `\\`, `\n`,
+/// `\r` and `\t`, so a line-based file can hold any path; see
+/// `unescape_field`.
+pub(crate) fn escape_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+/// Undo `escape_field`. A backslash before anything else is kept as is.
+pub(crate) fn unescape_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('\\')) => '\\',
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('r')) => '\r',
+            ('\\', Some('t')) => '\t',
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        out.push(escaped);
+    }
+    out
+}
+
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
     // Nanosecond suffix keeps concurrent writers (UI + tracker instance in
     // the same session) from interleaving writes into one temp file.
     let nanos = std::time::SystemTime::now()
@@ -162,6 +194,7 @@ fn tmp_path(path: &Path) -> PathBuf {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::scratch;
 
     fn live(names: &[&str]) -> BTreeSet<String> {
         names.iter().map(ToString::to_string).collect()
@@ -318,10 +351,7 @@ mod test {
 
     #[test]
     fn write_then_read_round_trips() {
-        // The wasm test runner maps only the project dir (see
-        // .cargo/config.toml), so scratch files must live under it.
-        let dir = PathBuf::from("target/zps-stack-test");
-        std::fs::create_dir_all(&dir).unwrap();
+        let dir = scratch("zps-stack-test", &[]);
         let path = dir.join("session-stack.v1");
         let mut stack = SessionStack::default();
         stack.push_top("alpha");
@@ -0,0 +1,16 @@
+//! Helpers shared by the modules' tests.
+
+use std::path::PathBuf;
+
+/// An empty directory `target/<name>` holding `dirs`. The wasm test runner
+/// maps only the project dir (see .cargo/config.toml), so scratch trees
+/// must live under it.
+pub(crate) fn scratch(name: &str, dirs: &[&str]) -> PathBuf {
+    let dir = PathBuf::from("target").join(name);
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    for d in dirs {
+        std::fs::create_dir_all(dir.join(d)).unwrap();
+    }
+    dir
+}
@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+use crate::core::normalise_path as normalise;
+
+/// A linked `git worktree` checkout: its `.git` is a file pointing into the
+/// main repository's `.git/worktrees/<id>` directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worktree {
+    /// The main checkout the worktree belongs to.
+    pub main: String,
+    /// The worktree's directory inside the main repository's `.git`.
+    pub gitdir: String,
+    /// The checked-out branch, once HEAD has been read.
+    pub branch: Option<String>,
+}
+
+impl Worktree {
+    /// Interpret the contents of the `.git` file at `path/.git`. None when it
+    /// is not a worktree pointer (submodules use `.git` files too).
+    #[must_use]
+    pub fn from_git_file(path: &str, contents: &str) -> Option<Worktree> {
+        let gitdir = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("gitdir:"))?
+            .trim();
+        let gitdir = if gitdir.starts_with('/') {
+            normalise(gitdir)
+        } else {
+            normalise(&format!("{path}/{gitdir}"))
+        };
+        let (repo_git, id) = gitdir.rsplit_once("/worktrees/")?;
+        if id.is_empty() || id.contains('/') {
+            return None;
+        }
+        let main = repo_git.strip_suffix("/.git")?;
+        Some(Worktree {
+            main: main.to_string(),
+            gitdir: gitdir.clone(),
+            branch: None,
+        })
+    }
+
+    /// The worktree's id, which git derives from its directory name; used in
+    /// place of the branch until HEAD is known.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        self.gitdir.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// The main project's name in `name`, this worktree's project name.
+    #[must_use]
+    pub fn main_name<'a>(&self, name: &'a str) -> Option<&'a str> {
+        name.strip_suffix(self.branch.as_deref().unwrap_or(self.id()))?
+            .strip_suffix('@')
+    }
+
+    /// The project name for this worktree, `<main project>@<branch>`.
+    #[must_use]
+    pub fn name(&self, main_name: &str) -> String {
+        format!(
+            "{main_name}@{}",
+            self.branch.as_deref().unwrap_or(self.id())
+        )
+    }
+}
+
+/// The branch named by a HEAD file: the branch for `ref: refs/heads/...`,
+/// otherwise the abbreviated commit of a detached HEAD.
+#[must_use]
+pub fn parse_head(contents: &str) -> Option<String> {
+    let head = contents.trim();
+    if let Some(reference) = head.strip_prefix("ref:") {
+        let reference = reference.trim();
+        return Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        );
+    }
+    if head.len() >= 7 && head.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(head[..7].to_string());
+    }
+    None
+}
+
+/// What a directory's `.git` turned out to be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitFile {
+    /// `.git` cannot be read as a file: an ordinary repository, which fd
+    /// releases that print directories without a trailing slash report like
+    /// a worktree.
+    Repository,
+    /// A linked worktree, with its branch read from HEAD.
+    Worktree(Worktree),
+    /// Some other `.git` file, such as a submodule's.
+    Other,
+}
+
+/// Read `path/.git`, and a worktree's HEAD, below `mount`, which the host
+/// has mapped to `/`.
+#[must_use]
+pub fn read_git_file(mount: &Path, path: &str) -> GitFile {
+    let host = |path: &str| mount.join(path.trim_start_matches('/'));
+    let Ok(contents) = std::fs::read_to_string(host(path).join(".git")) else {
+        return GitFile::Repository;
+    };
+    let Some(mut worktree) = Worktree::from_git_file(path, &contents) else {
+        return GitFile::Other;
+    };
+    let branch = std::fs::read_to_string(host(&worktree.gitdir).join("HEAD"))
+        .ok()
+        .and_then(|head| parse_head(&head));
+    worktree.branch = Some(branch.unwrap_or_else(|| worktree.id().to_string()));
+    GitFile::Worktree(worktree)
+}
+
+/// List order that keeps each worktree directly below its main checkout.
+/// `main_of` gives a worktree's main checkout's name, and None for any
+/// other project: names are not parsed, as a directory name may contain
+/// `@` too.
+#[must_use]
+pub fn group_order<F>(a: &str, b: &str, main_of: F) -> Ordering
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let key = |name: &str| match main_of(name) {
+        Some(main) => (main, Some(name.to_string())),
+        None => (name.to_string(), None),
+    };
+    key(a).cmp(&key(b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::scratch;
+
+    #[test]
+    fn git_file_with_absolute_gitdir() {
+        let wt = Worktree::from_git_file(
+            "/src/api-feature",
+            "gitdir: /src/api/.git/worktrees/api-feature\n",
+        )
+        .unwrap();
+        assert_eq!(wt.main, "/src/api");
+        assert_eq!(wt.id(), "api-feature");
+        assert_eq!(wt.branch, None);
+    }
+
+    #[test]
+    fn git_file_with_relative_gitdir() {
+        let wt = Worktree::from_git_file(
+            "/src/trees/feature",
+            "gitdir: ../../api/.git/worktrees/feature\n",
+        )
+        .unwrap();
+        assert_eq!(wt.main, "/src/api");
+        assert_eq!(wt.gitdir, "/src/api/.git/worktrees/feature");
+    }
+
+    #[test]
+    fn submodule_git_file_is_not_a_worktree() {
+        assert_eq!(
+            Worktree::from_git_file("/src/api/vendor/lib", "gitdir: ../../.git/modules/lib\n"),
+            None
+        );
+        assert_eq!(Worktree::from_git_file("/src/api", "garbage"), None);
+    }
+
+    #[test]
+    fn name_prefers_branch_over_id() {
+        let mut wt =
+            Worktree::from_git_file("/src/wt", "gitdir: /src/api/.git/worktrees/wt").unwrap();
+        assert_eq!(wt.name("api"), "api@wt");
+        assert_eq!(wt.main_name("api@wt"), Some("api"));
+        wt.branch = Some(String::from("feature/login"));
+        assert_eq!(wt.name("api"), "api@feature/login");
+        assert_eq!(wt.main_name("me@box@feature/login"), Some("me@box"));
+        assert_eq!(wt.main_name("api"), None);
+    }
+
+    #[test]
+    fn head_names_branch_or_short_commit() {
+        assert_eq!(
+            parse_head("ref: refs/heads/feature/login\n"),
+            Some(String::from("feature/login"))
+        );
+        assert_eq!(
+            parse_head("0123456789abcdef0123456789abcdef01234567\n"),
+            Some(String::from("0123456"))
+        );
+        assert_eq!(parse_head("nonsense"), None);
+    }
+
+    #[test]
+    fn worktrees_sort_below_their_main_checkout() {
+        let main_of = |name: &str| {
+            ["api@main", "api@dev"]
+                .contains(&name)
+                .then(|| String::from("api"))
+        };
+        let mut names = vec!["api@main", "api-client", "api", "api@dev", "web", "ab@c"];
+        names.sort_by(|a, b| group_order(a, b, main_of));
+        assert_eq!(
+            names,
+            vec!["ab@c", "api", "api@dev", "api@main", "api-client", "web"]
+        );
+    }
+
+    #[test]
+    fn read_git_file_tells_repositories_worktrees_and_submodules_apart() {
+        let mount = scratch(
+            "zps-worktree-test",
+            &[
+                "src/api/.git/worktrees/feature",
+                "src/api/.git/worktrees/detached",
+                "src/feature",
+                "src/detached",
+                "src/api/vendor/lib",
+            ],
+        );
+        let write =
+            |file: &str, contents: &str| std::fs::write(mount.join(file), contents).unwrap();
+        write(
+            "src/feature/.git",
+            "gitdir: /src/api/.git/worktrees/feature\n",
+        );
+        write(
+            "src/api/.git/worktrees/feature/HEAD",
+            "ref: refs/heads/login\n",
+        );
+        write(
+            "src/detached/.git",
+            "gitdir: ../api/.git/worktrees/detached\n",
+        );
+        write(
+            "src/api/vendor/lib/.git",
+            "gitdir: ../../.git/modules/lib\n",
+        );
+
+        assert_eq!(read_git_file(&mount, "/src/api"), GitFile::Repository);
+        assert_eq!(read_git_file(&mount, "/src/gone"), GitFile::Repository);
+        assert_eq!(read_git_file(&mount, "/src/api/vendor/lib"), GitFile::Other);
+        let GitFile::Worktree(feature) = read_git_file(&mount, "/src/feature") else {
+            panic!("not a worktree");
+        };
+        assert_eq!(feature.main, "/src/api");
+        assert_eq!(feature.branch.as_deref(), Some("login"));
+        // Without a readable HEAD the worktree is named by its id.
+        let GitFile::Worktree(detached) = read_git_file(&mount, "/src/detached") else {
+            panic!("not a worktree");
+        };
+        assert_eq!(detached.branch.as_deref(), Some("detached"));
+        std::fs::remove_dir_all(&mount).ok();
+    }
+}
@@ -0,0 +1,196 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::core::normalise_path;
+use crate::markers::Markers;
+use crate::worktree;
+
+/// Lists every directory zoxide knows with its score.
+pub const QUERY: [&str; 4] = ["zoxide", "query", "-l", "-s"];
+
+/// The `zoxide` settings: `zoxide "true"` lists the directories zoxide knows
+/// that are projects and ranks projects by their score;
+/// `zoxide_min_score "N"` also lists any directory scoring at least `N`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zoxide {
+    pub min_score: Option<f64>,
+}
+
+impl Zoxide {
+    /// None unless `zoxide` is `"true"`. An unparseable minimum score is
+    /// ignored.
+    #[must_use]
+    pub fn from_config(config: &BTreeMap<String, String>) -> Option<Zoxide> {
+        if config.get("zoxide").map(|v| v.trim()) != Some("true") {
+            return None;
+        }
+        let min_score = config
+            .get("zoxide_min_score")
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|s: &f64| s.is_finite());
+        Some(Zoxide { min_score })
+    }
+
+    /// Whether a directory scores high enough to be listed whether or not it
+    /// is a project.
+    #[must_use]
+    pub fn lists_any(&self, score: f64) -> bool {
+        self.min_score.is_some_and(|min| score >= min)
+    }
+}
+
+/// Parse `zoxide query -l -s` output, `<score> <path>` lines with the score
+/// right-aligned. Malformed lines are skipped.
+#[must_use]
+pub fn parse_scores(output: &str) -> BTreeMap<String, f64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (score, path) = line.trim_start().split_once(' ')?;
+            let score: f64 = score.parse().ok().filter(|s: &f64| s.is_finite())?;
+            let path = path.trim_start();
+            path.starts_with('/').then(|| (normalise_path(path), score))
+        })
+        .collect()
+}
+
+/// The markers directly inside each of `paths`, read below `mount`, which
+/// the host has mapped to `/`, as discovery output entries: this tells
+/// which of zoxide's directories are projects. Directories that have gone
+/// have none.
+#[must_use]
+pub fn read_markers(mount: &Path, paths: &[String], markers: &Markers) -> Vec<String> {
+    let mut found = Vec::new();
+    for path in paths {
+        let dir = mount.join(path.trim_start_matches('/'));
+        for name in markers.names() {
+            if let Ok(metadata) = std::fs::metadata(dir.join(name)) {
+                let slash = if metadata.is_dir() { "/" } else { "" };
+                found.push(format!("{path}/{name}{slash}"));
+            }
+        }
+    }
+    found
+}
+
+/// List order when the search does not rank projects: higher zoxide score
+/// first, then `worktree::group_order`. A worktree takes its main
+/// checkout's score so it stays below it; `score` maps a project name to
+/// its score and `main_of` a worktree's name to its main checkout's.
+#[must_use]
+pub fn score_order<F, M>(a: &str, b: &str, score: F, main_of: M) -> Ordering
+where
+    F: Fn(&str) -> f64,
+    M: Fn(&str) -> Option<String>,
+{
+    let main_score = |name: &str| main_of(name).map_or_else(|| score(name), |main| score(&main));
+    main_score(b)
+        .partial_cmp(&main_score(a))
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| worktree::group_order(a, b, &main_of))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::scratch;
+
+    /// Worktrees as `rebuild_projects` names them: `<main>@<branch>`.
+    fn main_of(name: &str) -> Option<String> {
+        ["api@dev"].contains(&name).then(|| String::from("api"))
+    }
+
+    fn config(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn zoxide_is_opt_in() {
+        assert_eq!(Zoxide::from_config(&BTreeMap::new()), None);
+        assert_eq!(Zoxide::from_config(&config(&[("zoxide", "no")])), None);
+        let zoxide = Zoxide::from_config(&config(&[("zoxide", "true")])).unwrap();
+        assert_eq!(zoxide.min_score, None);
+        assert!(!zoxide.lists_any(1000.0));
+    }
+
+    #[test]
+    fn min_score_lists_any_high_scoring_directory() {
+        let zoxide = Zoxide::from_config(&config(&[
+            ("zoxide", "true"),
+            ("zoxide_min_score", " 20.5 "),
+        ]))
+        .unwrap();
+        assert!(zoxide.lists_any(20.5));
+        assert!(!zoxide.lists_any(20.4));
+        let bogus = config(&[("zoxide", "true"), ("zoxide_min_score", "lots")]);
+        assert_eq!(Zoxide::from_config(&bogus).unwrap().min_score, None);
+    }
+
+    #[test]
+    fn scores_are_parsed_from_aligned_lines() {
+        assert_eq!(
+            parse_scores(" 120.5 /home/me/src/app\n   4.0 /home/me/my dir/\n  0.25 /tmp\ngarbage\nx /y\n  3 relative\n"),
+            BTreeMap::from([
+                (String::from("/home/me/src/app"), 120.5),
+                (String::from("/home/me/my dir"), 4.0),
+                (String::from("/tmp"), 0.25),
+            ])
+        );
+    }
+
+    #[test]
+    fn read_markers_checks_each_directory() {
+        let mount = scratch(
+            "zps-zoxide-test",
+            &["home/me/app/.git", "home/me/lib", "home/me/tree", "tmp"],
+        );
+        std::fs::write(mount.join("home/me/lib/Cargo.toml"), "").unwrap();
+        std::fs::write(mount.join("home/me/tree/.git"), "gitdir: /x").unwrap();
+        let markers = Markers::from_config(&config(&[("markers", ".git:Cargo.toml")]));
+        let paths: Vec<String> = [
+            "/home/me/app",
+            "/home/me/lib",
+            "/home/me/tree",
+            "/tmp",
+            "/gone",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(
+            read_markers(&mount, &paths, &markers),
+            vec![
+                "/home/me/app/.git/",
+                "/home/me/lib/Cargo.toml",
+                "/home/me/tree/.git"
+            ]
+        );
+        std::fs::remove_dir_all(&mount).ok();
+    }
+
+    #[test]
+    fn higher_scores_list_first_with_worktrees_below_their_checkout() {
+        let scores = BTreeMap::from([("api", 5.0), ("web", 50.0), ("me@box", 1.0)]);
+        let score = |name: &str| scores.get(name).copied().unwrap_or(0.0);
+        let mut names = vec!["api", "notes", "web", "api@dev", "docs", "me@box"];
+        names.sort_by(|a, b| score_order(a, b, score, main_of));
+        assert_eq!(
+            names,
+            vec!["web", "api", "api@dev", "me@box", "docs", "notes"]
+        );
+    }
+
+    #[test]
+    fn names_with_an_at_sign_keep_their_own_score() {
+        // `web@2` is a directory name, not a worktree of `web`.
+        let scores = BTreeMap::from([("web", 50.0), ("web@2", 1.0), ("api", 5.0)]);
+        let score = |name: &str| scores.get(name).copied().unwrap_or(0.0);
+        let mut names = vec!["web@2", "api", "web"];
+        names.sort_by(|a, b| score_order(a, b, score, |_| None));
+        assert_eq!(names, vec!["web", "api", "web@2"]);
+    }
+}
@@ -1,6 +1,6 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
 
-use zellij_project_switcher_plugin::core::CoreState;
+use zellij_project_switcher_plugin::core::{CoreState, SearchMode, SearchScope};
 
 #[test]
 fn it_inits_the_state() {
@@ -16,7 +16,7 @@ fn it_inits_the_state() {
     assert_eq!(state.projects, projects);
     assert_eq!(
         state.filtered_projects,
-        BTreeSet::from([String::from("alpha"), String::from("beta"),])
+        vec![String::from("alpha"), String::from("beta")]
     );
 
     assert_eq!(state.search_term, String::new());
@@ -56,18 +56,16 @@ fn it_updates_search_term_on_addition() {
     assert_eq!(state.search_term, String::from("b"));
     assert_eq!(
         state.filtered_projects,
-        BTreeSet::from([String::from("alphab"), String::from("beta")])
+        vec![String::from("beta"), String::from("alphab")]
     );
-    assert_eq!(state.selected_index, Some(0));
+    // The selection follows the project as it moves.
+    assert_eq!(state.selected_index, Some(1));
     assert_eq!(state.selected_item(), Some(String::from("alphab")));
 
     state.update_search_term('t');
 
     assert_eq!(state.search_term, String::from("bt"));
-    assert_eq!(
-        state.filtered_projects,
-        BTreeSet::from([String::from("beta")])
-    );
+    assert_eq!(state.filtered_projects, vec![String::from("beta")]);
     assert_eq!(state.selected_index, Some(0));
     assert_eq!(state.selected_item(), Some(String::from("beta")));
 }
@@ -82,15 +80,16 @@ fn it_updates_search_term_on_removal() {
         ]),
         current_session: String::from("default"),
         search_term: String::from("t"),
-        filtered_projects: BTreeSet::from([String::from("beta")]),
+        filtered_projects: vec![String::from("beta")],
         selected_index: Some(0),
+        ..CoreState::default()
     };
 
     state.update_search_term_backspace();
     assert_eq!(state.search_term, String::new());
     assert_eq!(
         state.filtered_projects,
-        BTreeSet::from([String::from("alpha"), String::from("beta")])
+        vec![String::from("alpha"), String::from("beta")]
     );
     assert_eq!(state.selected_index, Some(1));
     assert_eq!(state.selected_item(), Some(String::from("beta")));
@@ -158,6 +157,153 @@ fn it_updates_indexes_when_filtering() {
     assert_eq!(state.selected_item(), Some("other".to_string()));
 }
 
+#[test]
+fn it_ranks_matches_best_first() {
+    let projects: BTreeMap<String, String> = [
+        "a-big-pile-of-stuff",
+        "api",
+        "api-gateway",
+        "capital",
+        "default",
+        "rapid-api",
+    ]
+    .iter()
+    .map(|name| (name.to_string(), format!("/src/{name}")))
+    .collect();
+    let mut state = CoreState::init(&projects, String::from("default"));
+    for c in "api".chars() {
+        state.update_search_term(c);
+    }
+    assert_eq!(
+        state.filtered_projects,
+        vec![
+            "api",
+            "api-gateway",
+            "rapid-api",
+            "a-big-pile-of-stuff",
+            "capital"
+        ]
+    );
+}
+
+#[test]
+fn it_does_not_scroll_past_an_empty_list() {
+    let mut state = CoreState::init(
+        &BTreeMap::from([(String::from("alpha"), String::from("/alpha"))]),
+        String::from("default"),
+    );
+    state.update_search_term('z');
+    assert!(state.filtered_projects.is_empty());
+    state.down();
+    assert_eq!(state.selected_item(), None);
+}
+
+#[test]
+fn it_matches_regex_characters_literally() {
+    let mut state = CoreState::init(
+        &BTreeMap::from([
+            (String::from("c++-tools"), String::from("/src/c++-tools")),
+            (String::from("ctools"), String::from("/src/ctools")),
+        ]),
+        String::from("default"),
+    );
+    for c in "c++(".chars() {
+        state.update_search_term(c);
+    }
+    assert!(state.filtered_projects.is_empty());
+    state.update_search_term_backspace();
+    assert_eq!(state.filtered_projects, vec![String::from("c++-tools")]);
+    assert_eq!(state.search_error, None);
+}
+
+#[test]
+fn it_reports_invalid_regexes_and_keeps_the_list() {
+    let mut state = CoreState::init(
+        &BTreeMap::from([
+            (String::from("api"), String::from("/src/api")),
+            (String::from("web"), String::from("/src/web")),
+        ]),
+        String::from("default"),
+    );
+    state.toggle_search_mode();
+    assert_eq!(state.search_mode, SearchMode::Regex);
+    for c in "^a(".chars() {
+        state.update_search_term(c);
+    }
+    assert_eq!(state.search_error.as_deref(), Some("unclosed group"));
+    assert_eq!(state.filtered_projects, vec![String::from("api")]);
+    state.update_search_term(')');
+    assert_eq!(state.search_error, None);
+    assert_eq!(state.filtered_projects, vec![String::from("api")]);
+    state.toggle_search_mode();
+    assert!(state.filtered_projects.is_empty());
+}
+
+#[test]
+fn it_filters_with_the_extended_query_syntax() {
+    let projects: BTreeMap<String, String> = [
+        "api",
+        "api-gateway",
+        "api-tests",
+        "default",
+        "old-api",
+        "web",
+    ]
+    .iter()
+    .map(|name| (name.to_string(), format!("/src/{name}")))
+    .collect();
+    let mut state = CoreState::init(&projects, String::from("default"));
+    for c in "api !test !^old".chars() {
+        state.update_search_term(c);
+    }
+    assert_eq!(
+        state.filtered_projects,
+        vec![String::from("api"), String::from("api-gateway")]
+    );
+    for c in " way$".chars() {
+        state.update_search_term(c);
+    }
+    assert_eq!(state.filtered_projects, vec![String::from("api-gateway")]);
+}
+
+#[test]
+fn it_searches_paths_when_the_scope_includes_them() {
+    let mut state = CoreState::init(
+        &BTreeMap::from([
+            (String::from("api"), String::from("/clients/acme/api")),
+            (String::from("acme-site"), String::from("/src/acme-site")),
+            (String::from("web"), String::from("/src/web")),
+        ]),
+        String::from("default"),
+    );
+    for c in "acme".chars() {
+        state.update_search_term(c);
+    }
+    assert_eq!(state.filtered_projects, vec![String::from("acme-site")]);
+    state.cycle_search_scope();
+    assert_eq!(state.search_scope, SearchScope::RelativePath);
+    assert_eq!(
+        state.filtered_projects,
+        vec![String::from("acme-site"), String::from("api")]
+    );
+    state.cycle_search_scope();
+    state.cycle_search_scope();
+    assert_eq!(state.search_scope, SearchScope::Name);
+    assert_eq!(state.filtered_projects, vec![String::from("acme-site")]);
+}
+
+#[test]
+fn it_lists_nothing_for_host_words_without_roots() {
+    let mut state = CoreState::init(
+        &BTreeMap::from([(String::from("api"), String::from("/src/github.com/me/api"))]),
+        String::from("default"),
+    );
+    for c in "host:github.com api".chars() {
+        state.update_search_term(c);
+    }
+    assert!(state.filtered_projects.is_empty());
+}
+
 // fn trace<T>(state: &mut T, f: impl Fn(&mut T))
 // where
 //     T: std::fmt::Debug,